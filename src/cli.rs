@@ -0,0 +1,140 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use lexopt::{Arg, Parser, ValueExt};
+use log::LevelFilter;
+
+use crate::config::Config;
+use crate::logging::LogFormat;
+use crate::net::ListenAddress;
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+
+const HELP_TEXT: &str = "\
+Usage: {name} [OPTIONS]
+
+Options:
+  -c, --config <PATH>      Read the configuration from the given TOML file
+      --check-config       Validate the configuration and exit
+  -r, --root <PATH>        The path to serve files from
+  -L, --listen <ADDRESS>   Listen on the given address, e.g. 127.0.0.1:8080 or unix:/run/rusty_httpd.sock (repeatable)
+  -b, --bind <ADDRESS>     The local IP address where to listen for incoming connections
+  -p, --port <NUMBER>      The port number to listen for incoming connections
+  -t, --threads <COUNT>    The (minimum) number of worker threads
+      --max-threads <COUNT>
+                           The maximum number of worker threads
+      --timeout <MILLIS>   The I/O timeout for HTTP connections, in milliseconds
+      --backlog <COUNT>    The maximum number of connections waiting for a worker thread
+  -l, --log-level <LEVEL>  The log level (off, error, warn, info, debug, trace)
+      --log-format <FORMAT>
+                           The log output format (text, json)
+  -h, --help               Print this help text and exit
+  -V, --version            Print the version and exit
+
+Command-line options take precedence over environment variables, which take precedence over the configuration file.
+";
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub config_file: Option<PathBuf>,
+    pub check_config: bool,
+    log_level: Option<LevelFilter>,
+    log_format: Option<LogFormat>,
+    root: Option<PathBuf>,
+    listen: Vec<ListenAddress>,
+    bind: Option<IpAddr>,
+    port: Option<u16>,
+    threads: Option<NonZeroUsize>,
+    max_threads: Option<NonZeroUsize>,
+    timeout: Option<u64>,
+    backlog: Option<NonZeroUsize>,
+}
+
+impl Command {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(Parser::from_env()).map_err(|error| error.to_string())
+    }
+
+    fn parse(mut parser: Parser) -> Result<Self, lexopt::Error> {
+        let mut options = Options::default();
+        while let Some(arg) = parser.next()? {
+            match arg {
+                Arg::Short('c') | Arg::Long("config") => options.config_file = Some(parser.value()?.into()),
+                Arg::Long("check-config") => options.check_config = true,
+                Arg::Short('r') | Arg::Long("root") => options.root = Some(parser.value()?.into()),
+                Arg::Short('L') | Arg::Long("listen") => options.listen.push(parse_value(&mut parser, "--listen")?),
+                Arg::Short('b') | Arg::Long("bind") => options.bind = Some(parse_value(&mut parser, "--bind")?),
+                Arg::Short('p') | Arg::Long("port") => options.port = Some(parse_value(&mut parser, "--port")?),
+                Arg::Short('t') | Arg::Long("threads") => options.threads = Some(parse_value(&mut parser, "--threads")?),
+                Arg::Long("max-threads") => options.max_threads = Some(parse_value(&mut parser, "--max-threads")?),
+                Arg::Long("timeout") => options.timeout = Some(parse_value(&mut parser, "--timeout")?),
+                Arg::Long("backlog") => options.backlog = Some(parse_value(&mut parser, "--backlog")?),
+                Arg::Short('l') | Arg::Long("log-level") => options.log_level = Some(parse_value(&mut parser, "--log-level")?),
+                Arg::Long("log-format") => options.log_format = Some(parse_value(&mut parser, "--log-format")?),
+                Arg::Short('h') | Arg::Long("help") => return Ok(Self::Help),
+                Arg::Short('V') | Arg::Long("version") => return Ok(Self::Version),
+                _ => return Err(arg.unexpected()),
+            }
+        }
+        Ok(Self::Run(options))
+    }
+
+    pub fn help_text() -> String {
+        HELP_TEXT.replace("{name}", PKG_NAME)
+    }
+}
+
+impl Options {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(root) = self.root.as_ref() {
+            config.public_path = Some(root.clone());
+        }
+        if !self.listen.is_empty() {
+            config.listen = self.listen.clone();
+        }
+        if self.log_level.is_some() {
+            config.log_level = self.log_level;
+        }
+        override_value(&mut config.log_format, self.log_format);
+        override_value(&mut config.bind_address, self.bind);
+        override_value(&mut config.port_number, self.port);
+        override_value(&mut config.timeout, self.timeout);
+        if self.threads.is_some() {
+            config.threads = self.threads;
+        }
+        if self.max_threads.is_some() {
+            config.max_threads = self.max_threads;
+        }
+        if self.backlog.is_some() {
+            config.backlog = self.backlog;
+        }
+    }
+}
+
+fn parse_value<T>(parser: &mut Parser, name: &str) -> Result<T, lexopt::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parser.value()?.parse_with(|str| str.parse::<T>().map_err(|error| format!("{error} (for option '{name}')")))
+}
+
+fn override_value<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
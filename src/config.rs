@@ -0,0 +1,240 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::env;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::Error as IoError;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+use crate::logging::LogFormat;
+use crate::net::ListenAddress;
+
+const ILLEGAL_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+pub const DEFAULT_THREAD_IDLE_TIMEOUT: u64 = 60000;
+pub const DEFAULT_QUEUE_TIMEOUT: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub public_path: Option<PathBuf>,
+    pub log_level: Option<LevelFilter>,
+    pub log_format: LogFormat,
+    pub access_log: Option<PathBuf>,
+    pub access_log_format: String,
+    pub metrics_path: Option<String>,
+    pub listen: Vec<ListenAddress>,
+    #[serde(deserialize_with = "deserialize_socket_mode")]
+    pub socket_mode: Option<u32>,
+    pub bind_address: IpAddr,
+    pub port_number: u16,
+    pub backlog: Option<NonZeroUsize>,
+    pub threads: Option<NonZeroUsize>,
+    pub max_threads: Option<NonZeroUsize>,
+    pub thread_idle_timeout: u64,
+    pub timeout: u64,
+    pub keep_alive_timeout: u64,
+    pub keep_alive_max: NonZeroUsize,
+    pub shutdown_timeout: u64,
+    pub queue_timeout: u64,
+    pub queue_max_age: u64,
+    pub max_body_size: usize,
+    pub max_client_connections: Option<NonZeroUsize>,
+    pub client_request_rate: f64,
+    pub client_request_burst: Option<NonZeroU32>,
+    pub client_prefix_v4: u8,
+    pub client_prefix_v6: u8,
+    pub allow_encoded_slashes: bool,
+    pub directory_listing: bool,
+    #[serde(deserialize_with = "deserialize_index_files")]
+    pub index_files: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, IoError),
+    Syntax(PathBuf, Option<(usize, usize)>, String),
+    Variable(&'static str, String),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            public_path: None,
+            log_level: None,
+            log_format: LogFormat::Text,
+            access_log: None,
+            access_log_format: String::from("combined"),
+            metrics_path: None,
+            listen: Vec::new(),
+            socket_mode: None,
+            bind_address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port_number: 8080,
+            backlog: None,
+            threads: None,
+            max_threads: None,
+            thread_idle_timeout: DEFAULT_THREAD_IDLE_TIMEOUT,
+            timeout: 15000,
+            keep_alive_timeout: 5000,
+            keep_alive_max: NonZeroUsize::new(100).unwrap(),
+            shutdown_timeout: 10000,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            queue_max_age: 15000,
+            max_body_size: 1048576,
+            max_client_connections: None,
+            client_request_rate: 0.0,
+            client_request_burst: None,
+            client_prefix_v4: 32,
+            client_prefix_v6: 64,
+            allow_encoded_slashes: false,
+            directory_listing: false,
+            index_files: vec![String::from("index.html")],
+        }
+    }
+}
+
+impl Config {
+    pub fn load(config_file: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match config_file {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_owned(), error))?;
+        toml::from_str(&text).map_err(|error| {
+            let position = error.span().map(|span| line_column(&text, span.start));
+            ConfigError::Syntax(path.to_owned(), position, error.message().trim_end().to_owned())
+        })
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(value) = env_var("HTTP_PUBLIC_PATH") {
+            self.public_path = Some(PathBuf::from(value));
+        }
+        override_option_from_env(&mut self.log_level, "HTTP_LOG_LEVEL")?;
+        override_from_env(&mut self.log_format, "HTTP_LOG_FORMAT")?;
+        if let Some(value) = env_var("HTTP_ACCESS_LOG") {
+            self.access_log = Some(PathBuf::from(value));
+        }
+        override_from_env(&mut self.access_log_format, "HTTP_ACCESS_LOG_FORMAT")?;
+        if let Some(value) = env_var("HTTP_METRICS_PATH") {
+            self.metrics_path = Some(value);
+        }
+        if let Some(value) = env_var("HTTP_LISTEN") {
+            self.listen = parse_list(&value).ok_or(ConfigError::Variable("HTTP_LISTEN", value))?;
+        }
+        if let Some(value) = env_var("HTTP_SOCKET_MODE") {
+            self.socket_mode = Some(parse_socket_mode(&value).ok_or(ConfigError::Variable("HTTP_SOCKET_MODE", value))?);
+        }
+        override_from_env(&mut self.bind_address, "HTTP_BIND_ADDRESS")?;
+        override_from_env(&mut self.port_number, "HTTP_PORT_NUMBER")?;
+        override_option_from_env(&mut self.backlog, "HTTP_BACKLOG")?;
+        override_option_from_env(&mut self.threads, "HTTP_THREADS")?;
+        override_option_from_env(&mut self.max_threads, "HTTP_MAX_THREADS")?;
+        override_from_env(&mut self.thread_idle_timeout, "HTTP_THREAD_IDLE_TIMEOUT")?;
+        override_from_env(&mut self.timeout, "HTTP_TIMEOUT")?;
+        override_from_env(&mut self.keep_alive_timeout, "HTTP_KEEP_ALIVE_TIMEOUT")?;
+        override_from_env(&mut self.keep_alive_max, "HTTP_KEEP_ALIVE_MAX")?;
+        override_from_env(&mut self.shutdown_timeout, "HTTP_SHUTDOWN_TIMEOUT")?;
+        override_from_env(&mut self.queue_timeout, "HTTP_QUEUE_TIMEOUT")?;
+        override_from_env(&mut self.queue_max_age, "HTTP_QUEUE_MAX_AGE")?;
+        override_from_env(&mut self.max_body_size, "HTTP_MAX_BODY_SIZE")?;
+        override_option_from_env(&mut self.max_client_connections, "HTTP_MAX_CLIENT_CONNECTIONS")?;
+        override_from_env(&mut self.client_request_rate, "HTTP_CLIENT_REQUEST_RATE")?;
+        override_option_from_env(&mut self.client_request_burst, "HTTP_CLIENT_REQUEST_BURST")?;
+        override_from_env(&mut self.client_prefix_v4, "HTTP_CLIENT_PREFIX_V4")?;
+        override_from_env(&mut self.client_prefix_v6, "HTTP_CLIENT_PREFIX_V6")?;
+        override_from_env(&mut self.allow_encoded_slashes, "HTTP_ALLOW_ENCODED_SLASHES")?;
+        override_from_env(&mut self.directory_listing, "HTTP_DIRECTORY_LISTING")?;
+        if let Some(value) = env_var("HTTP_INDEX_FILES") {
+            self.index_files = parse_index_files(&value).ok_or(ConfigError::Variable("HTTP_INDEX_FILES", value))?;
+        }
+        Ok(())
+    }
+
+    pub fn listen_addresses(&self) -> Vec<ListenAddress> {
+        match self.listen.is_empty() {
+            true => vec![ListenAddress::Tcp(SocketAddr::new(self.bind_address, self.port_number))],
+            false => self.listen.clone(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io(path, error) => write!(f, "Failed to read configuration file {:?}: {error}", path),
+            Self::Syntax(path, Some((line, column)), message) => write!(f, "{}:{line}:{column}: {message}", path.display()),
+            Self::Syntax(path, None, message) => write!(f, "{}: {message}", path.display()),
+            Self::Variable(name, value) => write!(f, "Invalid value for environment variable {name}: {:?}", value),
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn override_from_env<T: FromStr>(target: &mut T, name: &'static str) -> Result<(), ConfigError> {
+    if let Some(value) = env_var(name) {
+        *target = value.trim().parse().map_err(|_| ConfigError::Variable(name, value))?;
+    }
+    Ok(())
+}
+
+fn override_option_from_env<T: FromStr>(target: &mut Option<T>, name: &'static str) -> Result<(), ConfigError> {
+    if let Some(value) = env_var(name) {
+        *target = Some(value.trim().parse().map_err(|_| ConfigError::Variable(name, value))?);
+    }
+    Ok(())
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Option<Vec<T>> {
+    value.split(',').map(str::trim).filter(|str| !str.is_empty()).map(|str| str.parse().ok()).collect()
+}
+
+fn parse_index_files(value: &str) -> Option<Vec<String>> {
+    value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(|name| is_valid_filename(name).then(|| name.to_owned())).collect()
+}
+
+fn deserialize_index_files<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    match names.iter().find(|name| !is_valid_filename(name)) {
+        Some(name) => Err(D::Error::custom(format!("invalid index file name {:?}", name))),
+        None => Ok(names),
+    }
+}
+
+fn parse_socket_mode(value: &str) -> Option<u32> {
+    u32::from_str_radix(value.trim().trim_start_matches("0o"), 8).ok().filter(|mode| *mode <= 0o777)
+}
+
+fn deserialize_socket_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match u32::deserialize(deserializer)? {
+        mode if mode <= 0o777 => Ok(Some(mode)),
+        mode => Err(D::Error::custom(format!("invalid socket mode {mode:o}, expected an octal number like 0o660"))),
+    }
+}
+
+fn is_valid_filename(name: &str) -> bool {
+    !(name.is_empty() || name.contains(ILLEGAL_CHARS) || name.starts_with('.'))
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let prefix = &text[..offset.min(text.len())];
+    let line_start = prefix.rfind('\n').map_or(0, |index| index + 1);
+    (prefix.matches('\n').count() + 1, prefix[line_start..].chars().count() + 1)
+}
@@ -0,0 +1,288 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
+use std::time::Duration;
+
+use mtcp_rs::TcpError;
+
+use crate::net::Stream;
+
+const MAX_LINE_LENGTH: usize = 4096;
+const MAX_TRAILER_LENGTH: usize = 65536;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+}
+
+#[derive(Debug, Default)]
+pub struct Remainder {
+    consumed: usize,
+    surplus: Vec<u8>,
+}
+
+struct BodyReader<'a> {
+    stream: &'a mut Stream,
+    pending: &'a [u8],
+    surplus: Vec<u8>,
+    consumed: usize,
+    timeout: Option<Duration>,
+}
+
+impl Remainder {
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    pub fn into_surplus(self) -> Vec<u8> {
+        self.surplus
+    }
+}
+
+pub fn read_body(stream: &mut Stream, pending: &[u8], framing: Framing, limit: usize, timeout: Option<Duration>) -> Result<(Vec<u8>, Remainder), BodyError> {
+    let mut reader = BodyReader {
+        stream,
+        pending,
+        surplus: Vec::new(),
+        consumed: 0,
+        timeout,
+    };
+
+    let body = match framing {
+        Framing::Empty => Vec::new(),
+        Framing::Length(length) => {
+            let length = usize::try_from(length).ok().filter(|length| *length <= limit).ok_or(BodyError::TooLarge)?;
+            let mut body = Vec::with_capacity(length);
+            reader.read_exact(&mut body, length)?;
+            body
+        },
+        Framing::Chunked => reader.read_chunked(limit)?,
+    };
+
+    Ok((body, Remainder { consumed: reader.consumed, surplus: reader.surplus }))
+}
+
+impl<'a> BodyReader<'a> {
+    fn fill(&mut self) -> Result<&[u8], BodyError> {
+        if self.consumed < self.pending.len() {
+            return Ok(&self.pending[self.consumed..]);
+        }
+        if self.surplus.is_empty() {
+            let mut temp = [0u8; 8192];
+            match self.stream.read_timeout(&mut temp, self.timeout) {
+                Ok(0) => return Err(BodyError::Incomplete),
+                Ok(length) => self.surplus.extend_from_slice(&temp[..length]),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(&self.surplus[..])
+    }
+
+    fn consume(&mut self, length: usize) {
+        if self.consumed < self.pending.len() {
+            self.consumed += length;
+        } else {
+            self.surplus.drain(..length);
+        }
+    }
+
+    fn read_exact(&mut self, output: &mut Vec<u8>, mut length: usize) -> Result<(), BodyError> {
+        while length > 0 {
+            let available = self.fill()?;
+            let count = available.len().min(length);
+            output.extend_from_slice(&available[..count]);
+            self.consume(count);
+            length -= count;
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, BodyError> {
+        let mut line = Vec::new();
+        loop {
+            let available = self.fill()?;
+            match available.iter().position(|&c| c == b'\n') {
+                Some(position) => {
+                    line.extend_from_slice(&available[..position]);
+                    self.consume(position + 1);
+                    return match line.pop() {
+                        Some(b'\r') => Ok(line),
+                        _ => Err(BodyError::Chunk),
+                    };
+                },
+                None => {
+                    let count = available.len();
+                    line.extend_from_slice(available);
+                    self.consume(count);
+                },
+            }
+            if line.len() > MAX_LINE_LENGTH {
+                return Err(BodyError::Chunk);
+            }
+        }
+    }
+
+    fn read_chunked(&mut self, limit: usize) -> Result<Vec<u8>, BodyError> {
+        let mut body = Vec::new();
+        loop {
+            let size = parse_chunk_size(&self.read_line()?)?;
+            if size == 0 {
+                break;
+            }
+            let size = usize::try_from(size).ok().filter(|size| body.len().saturating_add(*size) <= limit).ok_or(BodyError::TooLarge)?;
+            self.read_exact(&mut body, size)?;
+            if !self.read_line()?.is_empty() {
+                return Err(BodyError::Chunk);
+            }
+        }
+        let mut trailer_length = 0usize;
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            trailer_length += line.len() + 2;
+            if trailer_length > MAX_TRAILER_LENGTH {
+                return Err(BodyError::TrailerTooLarge);
+            }
+        }
+        Ok(body)
+    }
+}
+
+fn parse_chunk_size(line: &[u8]) -> Result<u64, BodyError> {
+    let size = line.split(|&c| c == b';').next().unwrap_or_default();
+    let size = std::str::from_utf8(size).map_err(|_| BodyError::Chunk)?.trim_matches([' ', '\t']);
+    if size.is_empty() || (size.len() > 16) || !size.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(BodyError::Chunk);
+    }
+    u64::from_str_radix(size, 16).map_err(|_| BodyError::Chunk)
+}
+
+pub enum BodyError {
+    Framing,
+    Unsupported,
+    Expectation,
+    TooLarge,
+    TrailerTooLarge,
+    Chunk,
+    Incomplete,
+    TimedOut,
+    Failed(IoError),
+}
+
+impl BodyError {
+    fn message(&self) -> &str {
+        match self {
+            Self::Framing => "Invalid Message Framing",
+            Self::Unsupported => "Unsupported Transfer Coding",
+            Self::Expectation => "Unsupported Expectation",
+            Self::TooLarge => "Payload Too Large",
+            Self::TrailerTooLarge => "Trailer Fields Too Large",
+            Self::Chunk => "Invalid Chunked Encoding",
+            Self::Incomplete => "Incomplete Body",
+            Self::TimedOut => "Timed Out",
+            Self::Failed(_) => "I/O Error",
+        }
+    }
+}
+
+impl From<TcpError> for BodyError {
+    fn from(error: TcpError) -> Self {
+        match error {
+            TcpError::TimedOut => Self::TimedOut,
+            other => Self::Failed(other.into()),
+        }
+    }
+}
+
+impl From<IoError> for BodyError {
+    fn from(error: IoError) -> Self {
+        TcpError::from(error).into()
+    }
+}
+
+impl Display for BodyError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Failed(error) => write!(f, "{}: {}", self.message(), error),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl Debug for BodyError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for BodyError {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    use crate::net::SocketStream;
+
+    fn read(data: &str, framing: Framing, limit: usize) -> Result<(String, usize), BodyError> {
+        let (local, _) = UnixStream::pair().unwrap();
+        let mut stream = Stream::from(SocketStream::Unix(local));
+        read_body(&mut stream, data.as_bytes(), framing, limit, None).map(|(body, remainder)| (String::from_utf8(body).unwrap(), remainder.consumed()))
+    }
+
+    #[test]
+    fn parse_chunk_sizes() {
+        assert_eq!(parse_chunk_size(b"1A").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"1a ; name=value").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"ffffffffffffffff").unwrap(), u64::MAX);
+        for line in [&b""[..], b"10000000000000000", b"0x10", b"-1", b"+1", b"1 2", b"\xff"] {
+            assert!(matches!(parse_chunk_size(line), Err(BodyError::Chunk)), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn read_chunks() {
+        let data = "5\r\nHello\r\n6;x=y\r\nWorld!\r\n0\r\nX-Checksum: abc\r\n\r\nGET";
+        let (body, consumed) = read(data, Framing::Chunked, 100).unwrap();
+        assert_eq!(body, "HelloWorld!");
+        assert_eq!(&data[consumed..], "GET");
+    }
+
+    #[test]
+    fn reject_chunk_size_overflow() {
+        assert!(matches!(read("ffffffffffffffff\r\n", Framing::Chunked, 100), Err(BodyError::TooLarge)));
+        assert!(matches!(read("10000000000000000\r\n", Framing::Chunked, 100), Err(BodyError::Chunk)));
+    }
+
+    #[test]
+    fn reject_oversize_bodies() {
+        assert!(matches!(read("6\r\nHello!\r\n6\r\nWorld!\r\n0\r\n\r\n", Framing::Chunked, 10), Err(BodyError::TooLarge)));
+        assert!(matches!(read("Hello World!", Framing::Length(12), 10), Err(BodyError::TooLarge)));
+        assert!(matches!(read("", Framing::Length(u64::MAX), 10), Err(BodyError::TooLarge)));
+        assert_eq!(read("Hello World!", Framing::Length(5), 10).unwrap(), (String::from("Hello"), 5));
+    }
+
+    #[test]
+    fn reject_malformed_chunks() {
+        assert!(matches!(read("5\r\nHello!\r\n0\r\n\r\n", Framing::Chunked, 100), Err(BodyError::Chunk)));
+        assert!(matches!(read("5\nHello\r\n0\r\n\r\n", Framing::Chunked, 100), Err(BodyError::Chunk)));
+        assert!(matches!(read("5\r\nHel", Framing::Chunked, 100), Err(BodyError::Incomplete)));
+    }
+
+    #[test]
+    fn reject_oversize_trailers() {
+        let field = format!("X-Padding: {}\r\n", "a".repeat(1000));
+        let accepted = format!("0\r\n{}\r\n", field.repeat(MAX_TRAILER_LENGTH / field.len()));
+        assert_eq!(read(&accepted, Framing::Chunked, 100).unwrap(), (String::new(), accepted.len()));
+        let rejected = format!("0\r\n{}\r\n", field.repeat(MAX_TRAILER_LENGTH / field.len() + 1));
+        assert!(matches!(read(&rejected, Framing::Chunked, 100), Err(BodyError::TrailerTooLarge)));
+    }
+}
@@ -18,8 +18,10 @@ pub enum ContentType {
     HTML,
     JavaScript,
     JPEG,
+    JSON,
     PDF,
     PNG,
+    Prometheus,
     Tar,
     Text,
     ZIP,
@@ -42,6 +44,7 @@ lazy_static! {
         builder.insert("jpeg", ContentType::JPEG);
         builder.insert("jpg", ContentType::JPEG);
         builder.insert("js", ContentType::JavaScript);
+        builder.insert("json", ContentType::JSON);
         builder.insert("pdf", ContentType::PDF);
         builder.insert("png", ContentType::PNG);
         builder.insert("tar", ContentType::Tar);
@@ -76,8 +79,10 @@ impl ContentType {
             Self::HTML => "text/html",
             Self::JavaScript => "text/javascript",
             Self::JPEG => "image/jpg",
+            Self::JSON => "application/json",
             Self::PDF => "application/pdf",
             Self::PNG => "image/png",
+            Self::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
             Self::Tar => "application/x-tar",
             Self::Text => "text/plain",
             Self::ZIP => "application/zip",
@@ -0,0 +1,144 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntityTag {
+    weak: bool,
+    tag: String,
+}
+
+impl EntityTag {
+    pub fn new(weak: bool, tag: &str) -> Self {
+        Self {
+            weak,
+            tag: tag.to_owned(),
+        }
+    }
+
+    pub fn from_metadata(file_info: &Metadata) -> Self {
+        let modified = file_info.modified().unwrap_or(UNIX_EPOCH);
+        let mtime = modified.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+        let weak = SystemTime::now().duration_since(modified).map_or(true, |age| age < Duration::from_secs(1));
+        Self {
+            weak,
+            tag: format!("{:x}-{:x}-{:x}", inode(file_info), file_info.len(), mtime),
+        }
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    pub fn strong_eq(&self, other: &Self) -> bool {
+        !(self.weak || other.weak) && (self.tag == other.tag)
+    }
+
+    pub fn weak_eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+
+    pub fn matches_any(&self, value: &str, weak_comparison: bool) -> bool {
+        if value.trim() == "*" {
+            return true;
+        }
+        parse_list(value).iter().any(|other| match weak_comparison {
+            true => self.weak_eq(other),
+            false => self.strong_eq(other),
+        })
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match parse_list(value).as_mut_slice() {
+            [single] => Some(std::mem::take(single)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for EntityTag {
+    fn default() -> Self {
+        Self::new(false, "")
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag),
+        }
+    }
+}
+
+fn parse_list(value: &str) -> Vec<EntityTag> {
+    let mut result = Vec::new();
+    let mut remaining = value.trim_start_matches([' ', '\t', ',']);
+    while !remaining.is_empty() {
+        let (weak, rest) = match remaining.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, remaining),
+        };
+        let Some((tag, rest)) = rest.strip_prefix('"').and_then(|rest| rest.split_once('"')) else {
+            break;
+        };
+        result.push(EntityTag::new(weak, tag));
+        remaining = rest.trim_start_matches([' ', '\t', ',']);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn inode(file_info: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    file_info.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_file_info: &Metadata) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_strong_and_weak() {
+        let strong = EntityTag::new(false, "abc");
+        let weak = EntityTag::new(true, "abc");
+        assert!(strong.strong_eq(&strong));
+        assert!(!strong.strong_eq(&weak) && !weak.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak) && weak.weak_eq(&weak));
+        assert!(!strong.weak_eq(&EntityTag::new(false, "xyz")));
+    }
+
+    #[test]
+    fn match_list() {
+        let strong = EntityTag::new(false, "abc");
+        assert!(strong.matches_any("\"xyz\", \"abc\"", false));
+        assert!(!strong.matches_any("W/\"abc\"", false));
+        assert!(strong.matches_any("W/\"abc\"", true));
+        assert!(!strong.matches_any("\"xyz\"", true));
+        assert!(!strong.matches_any("abc", true));
+    }
+
+    #[test]
+    fn match_wildcard() {
+        assert!(EntityTag::new(false, "abc").matches_any("*", false));
+        assert!(EntityTag::new(true, "abc").matches_any(" * ", false));
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(EntityTag::parse("W/\"abc\""), Some(EntityTag::new(true, "abc")));
+        assert_eq!(EntityTag::parse("\"abc\"").unwrap().to_string(), "\"abc\"");
+        assert_eq!(EntityTag::new(true, "abc").to_string(), "W/\"abc\"");
+        assert_eq!(EntityTag::parse("\"abc\", \"xyz\""), None);
+        assert_eq!(EntityTag::parse("abc"), None);
+    }
+}
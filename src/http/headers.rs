@@ -3,8 +3,9 @@
  * This is free and unencumbered software released into the public domain.
  */
 use core::str::Lines;
+use std::borrow::Cow;
 use std::convert::TryFrom;
-use std::io::{Error as IoError, ErrorKind};
+use crate::http::ParseError;
 use crate::utils::ValueMap;
 
 #[derive(Debug)]
@@ -13,35 +14,91 @@ pub struct Headers<'buf> {
 }
 
 impl<'buf> Headers<'buf> {
-    pub fn names(&'buf self) -> impl Iterator<Item = &'buf str> {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
         self.map.keys()
     }
 
-    pub fn values(&'buf self, name: &str) -> Option<impl Iterator<Item = &str>> {
-        self.map.values(name)
+    pub fn values(&self, name: &str) -> Option<impl Iterator<Item = &str>> {
+        self.map.values(&name.to_ascii_lowercase())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values(name).and_then(|mut values| values.next())
+    }
+
+    pub fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.values(name).is_some_and(|mut values| values.any(|value| value.split(',').map(str::trim).any(|item| item.eq_ignore_ascii_case(token))))
+    }
+}
+
+impl<'buf> Headers<'buf> {
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
     }
 }
 
 impl<'buf> TryFrom<Lines<'buf>> for Headers<'buf> {
-    type Error = IoError;
+    type Error = ParseError;
 
-    fn try_from(lines: Lines<'buf>) -> Result<Self, IoError> {
+    fn try_from(lines: Lines<'buf>) -> Result<Self, ParseError> {
         let mut map = ValueMap::new();
 
-        for line in lines.map(str::trim).take_while(|&str| !str.is_empty()) {
-            let mut parts = line.splitn(2, ':');
-            let key = parts.next().map(str::trim).unwrap_or_default();
-            let val = parts.next().map(str::trim).unwrap_or_default();
+        for line in lines.take_while(|&str| !str.trim().is_empty()) {
+            // obsolete line folding is not supported, see RFC 9112, section 5.2
+            if line.starts_with(|c: char| c.is_ascii_whitespace()) {
+                return Err(ParseError::Request);
+            }
 
-            if !key.is_empty() {
-                map.put(key.into(), val.into());
+            // no whitespace is allowed between field name and colon, see RFC 9112, section 5.1
+            let (key, val) = line.split_once(':').ok_or(ParseError::Request)?;
+            if key.is_empty() || !key.bytes().all(is_token_char) {
+                return Err(ParseError::Request);
             }
-        }
 
-        if map.is_empty() {
-            return Err(IoError::new(ErrorKind::NotFound, "No headers found!"));
+            map.put(lowercase(key), val.trim().into());
         }
 
         Ok (Self { map })
     }
 }
+
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+fn lowercase(str: &str) -> Cow<'_, str> {
+    if str.bytes().any(|c| c.is_ascii_uppercase()) {
+        str.to_ascii_lowercase().into()
+    } else {
+        str.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Headers<'_>, ParseError> {
+        Headers::try_from(text.lines())
+    }
+
+    #[test]
+    fn parse_fields() {
+        let headers = parse("Host: example.com\r\nAccept:  text/html \r\naccept: */*\r\n\r\n").unwrap();
+        assert_eq!(headers.get("host"), Some("example.com"));
+        assert_eq!(headers.values("Accept").unwrap().collect::<Vec<_>>(), ["text/html", "*/*"]);
+    }
+
+    #[test]
+    fn reject_whitespace_before_colon() {
+        assert!(matches!(parse("Transfer-Encoding : chunked\r\n\r\n"), Err(ParseError::Request)));
+        assert!(matches!(parse("Content-Length\t: 5\r\n\r\n"), Err(ParseError::Request)));
+    }
+
+    #[test]
+    fn reject_malformed_lines() {
+        assert!(matches!(parse("Host example.com\r\n\r\n"), Err(ParseError::Request)));
+        assert!(matches!(parse(": value\r\n\r\n"), Err(ParseError::Request)));
+        assert!(matches!(parse("Host: example.com\r\n folded\r\n\r\n"), Err(ParseError::Request)));
+    }
+}
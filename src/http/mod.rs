@@ -2,18 +2,27 @@
  * Rusty HTTP Server - simple and scalable HTTP server
  * This is free and unencumbered software released into the public domain.
  */
+pub use body::BodyError;
+pub use entity_tag::EntityTag;
 pub use method::Method;
 pub use query_string::QueryString;
+pub use range::RangeSet;
 pub use headers::Headers;
 pub use request::ParseError;
 pub use request::Request;
 pub use response::Response;
 pub use status_code::StatusCode;
+pub use version::Version;
 
+pub mod body;
+pub mod entity_tag;
 pub mod method;
+pub mod path;
 pub mod query_string;
+pub mod range;
 pub mod headers;
 pub mod request;
 pub mod response;
 pub mod status_code;
 pub mod content_type;
+pub mod version;
@@ -0,0 +1,95 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use urlencoding::{decode as url_decode, encode as url_encode};
+
+use super::ParseError;
+
+pub fn decode(raw_path: &str, allow_encoded_slashes: bool) -> Result<String, ParseError> {
+    let lowercase = raw_path.to_ascii_lowercase();
+    if raw_path.contains('\0') || lowercase.contains("%00") {
+        return Err(ParseError::Path);
+    }
+    if !allow_encoded_slashes && (lowercase.contains("%2f") || lowercase.contains("%5c")) {
+        return Err(ParseError::Path);
+    }
+    url_decode(raw_path).map(|decoded| decoded.into_owned()).map_err(|_| ParseError::Encoding)
+}
+
+pub fn encode(path: &str) -> String {
+    path.split('/').map(url_encode).collect::<Vec<_>>().join("/")
+}
+
+pub fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut directory = false;
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => directory = true,
+            ".." => {
+                segments.pop();
+                directory = true;
+            },
+            other => {
+                segments.push(other);
+                directory = false;
+            },
+        }
+    }
+
+    let mut result = String::with_capacity(path.len() + 1);
+    for segment in segments.iter() {
+        result.push('/');
+        result.push_str(segment);
+    }
+    if directory || segments.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(raw_path: &str) -> Result<String, ParseError> {
+        decode(raw_path, false).map(|path| normalize(&path))
+    }
+
+    #[test]
+    fn normalize_dot_segments() {
+        assert_eq!(normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize("/a/b/.."), "/a/");
+        assert_eq!(normalize("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize("//a//b/"), "/a/b/");
+        assert_eq!(normalize(""), "/");
+    }
+
+    #[test]
+    fn normalize_encoded_dot_segments() {
+        assert_eq!(resolve("/a/%2e%2e/b").unwrap(), "/b");
+        assert_eq!(resolve("/%2E%2E/%2e%2e/etc/passwd").unwrap(), "/etc/passwd");
+        assert_eq!(resolve("/a/%2e/b%20c").unwrap(), "/a/b c");
+    }
+
+    #[test]
+    fn reject_encoded_null() {
+        assert!(matches!(decode("/a%00.html", false), Err(ParseError::Path)));
+        assert!(matches!(decode("/a\0.html", true), Err(ParseError::Path)));
+    }
+
+    #[test]
+    fn reject_encoded_slashes() {
+        assert!(matches!(decode("/a%2fb", false), Err(ParseError::Path)));
+        assert!(matches!(decode("/a%2F..%2Fb", false), Err(ParseError::Path)));
+        assert!(matches!(decode("/a%5Cb", false), Err(ParseError::Path)));
+        assert_eq!(decode("/a%2Fb", true).unwrap(), "/a/b");
+    }
+
+    #[test]
+    fn encode_segments() {
+        assert_eq!(encode("/sub dir/a&b/"), "/sub%20dir/a%26b/");
+    }
+}
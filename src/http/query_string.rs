@@ -47,7 +47,7 @@ impl<'buf> TryFrom<&'buf str> for QueryString<'buf> {
     }
 }
 
-fn decode(str: &str) -> Cow<str> {
+fn decode(str: &str) -> Cow<'_, str> {
     match url_decode(str) {
         Ok(decoded) => decoded,
         Err(_) => str.into()
@@ -0,0 +1,150 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+const MAX_RANGE_COUNT: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug)]
+pub enum RangeSet {
+    Satisfiable(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+impl Display for ByteRange {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl RangeSet {
+    pub fn parse(value: &str, size: u64) -> Option<Self> {
+        let (unit, specs) = value.trim().split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+
+        let mut ranges = Vec::new();
+        for spec in specs.split(',').map(str::trim).filter(|str| !str.is_empty()) {
+            if let Some(range) = parse_spec(spec, size)? {
+                ranges.push(range);
+            }
+            if ranges.len() > MAX_RANGE_COUNT {
+                return None;
+            }
+        }
+
+        if ranges.is_empty() {
+            return Some(Self::Unsatisfiable);
+        }
+
+        Some(Self::Satisfiable(coalesce(ranges)))
+    }
+}
+
+fn parse_spec(spec: &str, size: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    match (first.is_empty(), last.is_empty()) {
+        (false, _) => {
+            let start = parse_number(first)?;
+            let end = if last.is_empty() { u64::MAX } else { parse_number(last)? };
+            if end < start {
+                return None;
+            }
+            Some((start < size).then(|| ByteRange { start, end: end.min(size - 1) }))
+        },
+        (true, false) => {
+            let suffix = parse_number(last)?;
+            Some((suffix > 0 && size > 0).then(|| ByteRange { start: size.saturating_sub(suffix), end: size - 1 }))
+        },
+        (true, true) => None,
+    }
+}
+
+fn parse_number(str: &str) -> Option<u64> {
+    str.bytes().all(|c| c.is_ascii_digit()).then(|| str.parse().ok()).flatten()
+}
+
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut result: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match result.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+            _ => result.push(range),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(value: &str, size: u64) -> Vec<(u64, u64)> {
+        match RangeSet::parse(value, size) {
+            Some(RangeSet::Satisfiable(ranges)) => ranges.iter().map(|range| (range.start(), range.end())).collect(),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_simple_ranges() {
+        assert_eq!(ranges("bytes=0-99", 1000), [(0, 99)]);
+        assert_eq!(ranges("bytes=900-", 1000), [(900, 999)]);
+        assert_eq!(ranges("bytes=900-5000", 1000), [(900, 999)]);
+    }
+
+    #[test]
+    fn parse_suffix_ranges() {
+        assert_eq!(ranges("bytes=-100", 1000), [(900, 999)]);
+        assert_eq!(ranges("bytes=-5000", 1000), [(0, 999)]);
+        assert!(matches!(RangeSet::parse("bytes=-0", 1000), Some(RangeSet::Unsatisfiable)));
+        assert!(matches!(RangeSet::parse("bytes=-100", 0), Some(RangeSet::Unsatisfiable)));
+    }
+
+    #[test]
+    fn coalesce_overlapping_ranges() {
+        assert_eq!(ranges("bytes=500-599, 0-99, 50-149", 1000), [(0, 149), (500, 599)]);
+        assert_eq!(ranges("bytes=0-99,100-199", 1000), [(0, 199)]);
+        assert_eq!(ranges("bytes=0-,-100", 1000), [(0, 999)]);
+    }
+
+    #[test]
+    fn skip_out_of_range() {
+        assert_eq!(ranges("bytes=0-99,1000-1099", 1000), [(0, 99)]);
+        assert!(matches!(RangeSet::parse("bytes=1000-1099", 1000), Some(RangeSet::Unsatisfiable)));
+        assert!(matches!(RangeSet::parse("bytes=0-", 0), Some(RangeSet::Unsatisfiable)));
+    }
+
+    #[test]
+    fn reject_invalid_ranges() {
+        assert!(RangeSet::parse("bytes=100-99", 1000).is_none());
+        assert!(RangeSet::parse("bytes=-", 1000).is_none());
+        assert!(RangeSet::parse("bytes=+1-2", 1000).is_none());
+        assert!(RangeSet::parse("items=0-99", 1000).is_none());
+        assert!(RangeSet::parse(&format!("bytes={}", vec!["0-0"; MAX_RANGE_COUNT + 1].join(",")), 1000).is_none());
+    }
+}
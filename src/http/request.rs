@@ -8,19 +8,32 @@ use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::str;
 use std::str::Utf8Error;
+use std::time::Duration;
+
+use crate::net::Stream;
 
 use regex::Regex;
+use regex::bytes::Regex as BytesRegex;
 
+use super::body::{self, BodyError, Framing, Remainder};
 use super::method::{Method, MethodError};
+use super::path;
 use super::QueryString;
 use super::Headers;
+use super::StatusCode;
+use super::Version;
+
+const MAX_URI_LENGTH: usize = 8192;
 
 #[derive(Debug)]
 pub struct Request<'buf> {
     method: Method,
+    target: &'buf str,
     path: &'buf str,
+    version: Version,
     query: Option<QueryString<'buf>>,
     headers: Option<Headers<'buf>>,
+    body: Option<Vec<u8>>,
 }
 
 impl<'buf> Request<'buf> {
@@ -28,17 +41,89 @@ impl<'buf> Request<'buf> {
         &self.method
     }
 
+    pub fn target(&self) -> &str {
+        self.target
+    }
+
     pub fn path(&self) -> &str {
         self.path
     }
 
-    pub fn headers(&self) -> Option<&Headers> {
+    pub fn decoded_path(&self, allow_encoded_slashes: bool) -> Result<String, ParseError> {
+        path::decode(self.path, allow_encoded_slashes).map(|decoded| path::normalize(&decoded))
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn headers(&self) -> Option<&Headers<'buf>> {
         self.headers.as_ref()
     }
 
-    pub fn query(&self) -> Option<&QueryString> {
+    pub fn query(&self) -> Option<&QueryString<'buf>> {
         self.query.as_ref()
     }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.as_ref().and_then(|headers| headers.get(name))
+    }
+
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+
+    pub fn framing(&self) -> Result<Framing, BodyError> {
+        let Some(headers) = self.headers.as_ref() else {
+            return Ok(Framing::Empty);
+        };
+        let length = headers.values("Content-Length").map(parse_content_length).transpose()?;
+        match headers.values("Transfer-Encoding") {
+            Some(values) => {
+                if length.is_some() || (self.version == Version::HTTP10) {
+                    return Err(BodyError::Framing);
+                }
+                let codings: Vec<&str> = values.flat_map(|value| value.split(',')).map(str::trim).filter(|str| !str.is_empty()).collect();
+                match codings.last() {
+                    Some(last) if last.eq_ignore_ascii_case("chunked") => match codings.len() {
+                        1 => Ok(Framing::Chunked),
+                        _ => Err(BodyError::Unsupported),
+                    },
+                    Some(_) => Err(BodyError::Framing),
+                    None => Err(BodyError::Unsupported),
+                }
+            },
+            None => Ok(length.filter(|length| *length > 0).map_or(Framing::Empty, Framing::Length)),
+        }
+    }
+
+    pub fn expects_continue(&self) -> Result<bool, BodyError> {
+        match self.header("Expect") {
+            Some(expect) if expect.eq_ignore_ascii_case("100-continue") => Ok(self.version == Version::HTTP11),
+            Some(_) => Err(BodyError::Expectation),
+            None => Ok(false),
+        }
+    }
+
+    pub fn read_body(&mut self, stream: &mut Stream, pending: &[u8], limit: usize, timeout: Option<Duration>) -> Result<Remainder, BodyError> {
+        let framing = self.framing()?;
+        if let Framing::Length(length) = framing {
+            if length > limit as u64 {
+                return Err(BodyError::TooLarge);
+            }
+        }
+        if self.expects_continue()? && (framing != Framing::Empty) && pending.is_empty() {
+            stream.write_all_timeout(b"HTTP/1.1 100 Continue\r\n\r\n", timeout)?;
+        }
+        let (data, remainder) = body::read_body(stream, pending, framing, limit, timeout)?;
+        self.body = (!data.is_empty()).then_some(data);
+        Ok(remainder)
+    }
+
+    pub fn parse(buf: &'buf [u8]) -> Result<(Request<'buf>, usize), ParseError> {
+        let length = header_length(buf).ok_or(ParseError::Request)?;
+        Ok((Self::try_from(&buf[..length])?, length))
+    }
 }
 
 impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
@@ -46,42 +131,83 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
 
     fn try_from(buf: &'buf [u8]) -> Result<Request<'buf>, Self::Error> {
         // break lines
-        let mut lines = str::from_utf8(buf)?.lines();
+        let mut lines = str::from_utf8(&buf[empty_lines_length(buf)..])?.lines();
 
         // split request line
         let mut request = lines.next().ok_or(ParseError::Request)?.split_ascii_whitespace();
 
         // parse request
         let method = request.next().ok_or(ParseError::Request)?;
-        let (path, query) = split(request.next().ok_or(ParseError::Request)?, "?");
+        let target = request.next().ok_or(ParseError::Request)?;
         let protocol = request.next().ok_or(ParseError::Request)?;
+        if request.next().is_some() {
+            return Err(ParseError::Request);
+        }
 
         // check protocol version
-        if !parse_protocol_version(protocol).map_or(false, |ver| (ver.0 == 1) && (ver.1 < 2)) {
-            return Err(ParseError::Protocol);
+        let version = parse_protocol_version(protocol).ok_or(ParseError::Protocol)?;
+        let version = Version::from_numbers(version.0, version.1).ok_or(ParseError::Version)?;
+
+        // check request target
+        if target.len() > MAX_URI_LENGTH {
+            return Err(ParseError::UriTooLong);
         }
+        let (path, query) = split(target, "?");
 
         // parse method, request headers and query
         let method: Method = method.parse()?;
         let query = query.map(QueryString::try_from).and_then(Result::ok);
-        let headers = Headers::try_from(lines).ok();
+        let headers = Some(Headers::try_from(lines)?).filter(|headers| !headers.is_empty());
 
         Ok(Self {
             method,
+            target,
             path,
+            version,
             headers,
             query,
+            body: None,
         })
     }
 }
 
+pub fn header_length(buf: &[u8]) -> Option<usize> {
+    lazy_static! {
+        static ref END_MARKER: BytesRegex = BytesRegex::new(r"\x0D\x0A[\x09\x0B\x0C\x20]*\x0D\x0A").unwrap();
+    }
+    let offset = empty_lines_length(buf);
+    END_MARKER.find(&buf[offset..]).map(|end_marker| offset + end_marker.end())
+}
+
+fn parse_content_length<'a>(values: impl Iterator<Item = &'a str>) -> Result<u64, BodyError> {
+    let mut result: Option<u64> = None;
+    for value in values.flat_map(|value| value.split(',')).map(str::trim) {
+        if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(BodyError::Framing);
+        }
+        let length = value.parse::<u64>().map_err(|_| BodyError::Framing)?;
+        if result.replace(length).is_some_and(|previous| previous != length) {
+            return Err(BodyError::Framing);
+        }
+    }
+    result.ok_or(BodyError::Framing)
+}
+
+fn empty_lines_length(buf: &[u8]) -> usize {
+    let mut offset = 0usize;
+    while buf[offset..].starts_with(b"\r\n") {
+        offset += 2;
+    }
+    offset
+}
+
 fn split<'a>(str: &'a str, pattern: &str) -> (&'a str, Option<&'a str>) {
     let mut parts = str.splitn(2, pattern);
     (parts.next().unwrap_or(str), parts.next())
 }
 
 fn get_next_word(request: &str) -> Option<(&str, &str)> {
-    for (i, c) in request.chars().enumerate() {
+    for (i, c) in request.char_indices() {
         if c == ' ' || c == '\r' {
             return Some((&request[..i], &request[i + 1..]));
         }
@@ -107,7 +233,10 @@ pub enum ParseError {
     Request,
     Encoding,
     Protocol,
+    Version,
     Method,
+    Path,
+    UriTooLong,
 }
 
 impl ParseError {
@@ -116,7 +245,19 @@ impl ParseError {
             Self::Request => "Invalid Request",
             Self::Encoding => "Invalid Encoding",
             Self::Protocol => "Invalid Protocol",
+            Self::Version => "Unsupported Protocol Version",
             Self::Method => "Invalid Method",
+            Self::Path => "Invalid Path",
+            Self::UriTooLong => "Request Target Too Long",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Request | Self::Encoding | Self::Protocol | Self::Path => StatusCode::BadRequest,
+            Self::Version => StatusCode::HttpVersionNotSupported,
+            Self::Method => StatusCode::NotImplemented,
+            Self::UriTooLong => StatusCode::UriTooLong,
         }
     }
 }
@@ -2,66 +2,105 @@
  * Rusty HTTP Server - simple and scalable HTTP server
  * This is free and unencumbered software released into the public domain.
  */
-use std::fmt::{Debug, Write};
+use std::fmt::{Debug, Display, Write};
 use std::fs::File;
-use std::io::{Result as IoResult, Read};
+use std::io::{Result as IoResult, Read, Seek, SeekFrom};
 use std::time::Duration;
 
-use mtcp_rs::{TcpStream, TcpError};
+use mtcp_rs::TcpError;
+
+use crate::net::Stream;
 
 use super::StatusCode;
 use super::content_type::ContentType;
+use super::range::ByteRange;
 
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[derive(Debug)]
 enum Body {
     File(File),
+    FileRange(File, ByteRange),
+    Multipart(File, Vec<(String, ByteRange)>, String),
     Str(&'static str),
     String(String),
     Buffer(Vec<u8>),
+    Stream(Box<dyn Read>),
+    Iter(Box<dyn Iterator<Item = Vec<u8>>>),
     None,
 }
 
 #[derive(Debug)]
-pub struct Response { 
+pub struct Response {
+    status_code: StatusCode,
     header: String,
     body: Body,
+    chunked: bool,
+    trailers: Vec<(String, String)>,
 }
 
 impl Response {
     pub fn new(status_code: StatusCode, size: Option<u64>, content_type: Option<ContentType>) -> Self {
-        Self {
-            header: Self::create_header(status_code, size, content_type),
-            body: Body::None,
-        }
+        Self::create(status_code, size, content_type, Body::None)
     }
 
     pub fn from_file(status_code: StatusCode, file: File, content_type: Option<ContentType>) -> Self {
-        Self {
-            header: Self::create_header(status_code, file_size(&file), content_type),
-            body: Body::File(file),
-        }
+        Self::create(status_code, file_size(&file), content_type, Body::File(file))
+    }
+
+    pub fn from_file_range(file: File, range: ByteRange, size: u64, content_type: Option<ContentType>) -> Self {
+        Self::create(StatusCode::PartialContent, Some(range.length()), content_type, Body::FileRange(file, range))
+            .with_header("Content-Range", format!("bytes {range}/{size}"))
+    }
+
+    pub fn from_file_ranges(file: File, ranges: Vec<ByteRange>, size: u64, content_type: Option<ContentType>) -> Self {
+        let boundary = format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>());
+        let parts: Vec<(String, ByteRange)> = ranges.into_iter().map(|range| {
+            let mut header = format!("--{boundary}\r\n");
+            if let Some(ctype) = content_type {
+                write!(header, "Content-Type: {}\r\n", ctype.as_ref()).unwrap();
+            }
+            write!(header, "Content-Range: bytes {range}/{size}\r\n\r\n").unwrap();
+            (header, range)
+        }).collect();
+        let closing = format!("--{boundary}--\r\n");
+        let length = parts.iter().map(|(header, range)| header.len() as u64 + range.length() + 2).sum::<u64>() + closing.len() as u64;
+        Self::create(StatusCode::PartialContent, Some(length), None, Body::Multipart(file, parts, closing))
+            .with_header("Content-Type", format!("multipart/byteranges; boundary={boundary}"))
     }
 
     pub fn from_text(status_code: StatusCode, text: &'static str, content_type: Option<ContentType>) -> Self {
-        Self {
-            header: Self::create_header(status_code, Some(text.len() as u64), content_type.or(Some(ContentType::Text))),
-            body: Body::Str(text),
-        }
+        Self::create(status_code, Some(text.len() as u64), content_type.or(Some(ContentType::Text)), Body::Str(text))
     }
 
     pub fn from_string(status_code: StatusCode, string: String, content_type: Option<ContentType>) -> Self {
-        Self {
-            header: Self::create_header(status_code, Some(string.len() as u64), content_type.or(Some(ContentType::Text))),
-            body: Body::String(string),
-        }
+        Self::create(status_code, Some(string.len() as u64), content_type.or(Some(ContentType::Text)), Body::String(string))
     }
 
     pub fn from_data(status_code: StatusCode, data: Vec<u8>, content_type: Option<ContentType>) -> Self {
+        Self::create(status_code, Some(data.len() as u64), content_type, Body::Buffer(data))
+    }
+
+    pub fn from_reader(status_code: StatusCode, reader: impl Read + 'static, content_type: Option<ContentType>) -> Self {
+        Self::create(status_code, None, content_type, Body::Stream(Box::new(reader)))
+    }
+
+    pub fn from_chunks<I, T>(status_code: StatusCode, chunks: I, content_type: Option<ContentType>) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: 'static,
+        T: Into<Vec<u8>> + 'static,
+    {
+        Self::create(status_code, None, content_type, Body::Iter(Box::new(chunks.into_iter().map(Into::into))))
+    }
+
+    fn create(status_code: StatusCode, length: Option<u64>, content_type: Option<ContentType>, body: Body) -> Self {
+        let chunked = body.is_streamed();
         Self {
-            header: Self::create_header(status_code, Some(data.len() as u64), content_type),
-            body: Body::Buffer(data),
+            status_code,
+            header: Self::create_header(status_code, length, content_type),
+            body,
+            chunked,
+            trailers: Vec::new(),
         }
     }
 
@@ -75,32 +114,133 @@ impl Response {
         if let Some(ctype) = content_type {
             write!(header, "Content-Type: {}\r\n", ctype.as_ref()).unwrap();
         }
-        write!(header, "\r\n").unwrap();
         header
     }
 
-    pub fn send(self, mut writer: TcpStream, timeout: Option<Duration>) -> IoResult<()> {
+    pub fn with_header(mut self, name: &str, value: impl Display) -> Self {
+        write!(self.header, "{name}: {value}\r\n").unwrap();
+        self
+    }
+
+    pub fn with_trailer(mut self, name: &str, value: impl Display) -> Self {
+        debug_assert!(self.chunked, "Trailers require a chunked response!");
+        if self.chunked {
+            self.trailers.push((name.to_owned(), value.to_string()));
+        }
+        self
+    }
+
+    pub fn without_body(mut self) -> Self {
+        self.body = Body::None;
+        self.chunked = false;
+        self.trailers.clear();
+        self
+    }
+
+    pub fn without_chunking(mut self) -> Self {
+        self.chunked = false;
+        self.trailers.clear();
+        self
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.body.is_streamed()
+    }
+
+    pub fn send(mut self, writer: &mut Stream, timeout: Option<Duration>) -> IoResult<()> {
+        if self.chunked {
+            self.header.push_str("Transfer-Encoding: chunked\r\n");
+            if !self.trailers.is_empty() {
+                let names: Vec<&str> = self.trailers.iter().map(|(name, _)| name.as_str()).collect();
+                write!(self.header, "Trailer: {}\r\n", names.join(", ")).unwrap();
+            }
+        }
+        self.header.push_str("\r\n");
         writer.write_all_timeout(self.header.as_bytes(), timeout)?;
-        self.body.send(writer, timeout)
+        writer.mark();
+        match self.chunked {
+            true => self.body.send_chunked(writer, &self.trailers, timeout),
+            false => self.body.send(writer, timeout),
+        }
     }
 }
 
 impl Body {
-    pub fn send(self, writer: TcpStream, timeout: Option<Duration>) -> IoResult<()> {
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, Self::Stream(_) | Self::Iter(_))
+    }
+
+    pub fn send(self, writer: &mut Stream, timeout: Option<Duration>) -> IoResult<()> {
         match self {
             Self::File(file) => Self::transfer_from_file(writer, file, timeout),
+            Self::FileRange(mut file, range) => Self::transfer_range(writer, &mut file, range, timeout),
+            Self::Multipart(mut file, parts, closing) => {
+                for (header, range) in parts {
+                    Self::transfer(writer, header.as_bytes(), timeout)?;
+                    Self::transfer_range(writer, &mut file, range, timeout)?;
+                    Self::transfer(writer, b"\r\n", timeout)?;
+                }
+                Self::transfer(writer, closing.as_bytes(), timeout)
+            },
             Self::Str(str) => Self::transfer(writer, str.as_bytes(), timeout),
             Self::String(string) => Self::transfer(writer, string.as_bytes(), timeout),
             Self::Buffer(buffer) => Self::transfer(writer, &buffer[..], timeout),
+            Self::Stream(reader) => Self::transfer_from_file(writer, reader, timeout),
+            Self::Iter(iter) => iter.filter(|data| !data.is_empty()).try_for_each(|data| Self::transfer(writer, &data[..], timeout)),
             Self::None => Ok(()),
         }
     }
 
-    fn transfer(mut writer: TcpStream, source: &[u8], timeout: Option<Duration>) -> IoResult<()> {
+    pub fn send_chunked(self, writer: &mut Stream, trailers: &[(String, String)], timeout: Option<Duration>) -> IoResult<()> {
+        let mut chunk = Vec::new();
+        match self {
+            Self::Stream(mut reader) => {
+                let mut temp = [0u8; 4096];
+                loop {
+                    match reader.read(&mut temp)? {
+                        0 => break,
+                        length => Self::transfer_chunk(writer, &mut chunk, &temp[0..length], timeout)?,
+                    }
+                }
+            },
+            Self::Iter(iter) => {
+                for data in iter.filter(|data| !data.is_empty()) {
+                    Self::transfer_chunk(writer, &mut chunk, &data[..], timeout)?;
+                }
+            },
+            other => return other.send(writer, timeout),
+        }
+        chunk.clear();
+        chunk.extend_from_slice(b"0\r\n");
+        for (name, value) in trailers {
+            chunk.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        chunk.extend_from_slice(b"\r\n");
+        Self::transfer(writer, &chunk[..], timeout)
+    }
+
+    fn transfer_chunk(writer: &mut Stream, chunk: &mut Vec<u8>, data: &[u8], timeout: Option<Duration>) -> IoResult<()> {
+        chunk.clear();
+        chunk.extend_from_slice(format!("{:X}\r\n", data.len()).as_bytes());
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(b"\r\n");
+        Self::transfer(writer, &chunk[..], timeout)
+    }
+
+    fn transfer(writer: &mut Stream, source: &[u8], timeout: Option<Duration>) -> IoResult<()> {
         writer.write_all_timeout(source, timeout).map_err(TcpError::into)
     }
 
-    fn transfer_from_file(mut writer: TcpStream, mut source: impl Read + 'static, timeout: Option<Duration>) -> IoResult<()> {
+    fn transfer_range(writer: &mut Stream, file: &mut File, range: ByteRange, timeout: Option<Duration>) -> IoResult<()> {
+        file.seek(SeekFrom::Start(range.start()))?;
+        Self::transfer_from_file(writer, file.take(range.length()), timeout)
+    }
+
+    fn transfer_from_file(writer: &mut Stream, mut source: impl Read, timeout: Option<Duration>) -> IoResult<()> {
         let mut temp = [0u8; 4096];
         loop {
             match source.read(&mut temp)? {
@@ -111,6 +251,100 @@ impl Body {
     }
 }
 
+impl Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(file) => f.debug_tuple("File").field(file).finish(),
+            Self::FileRange(file, range) => f.debug_tuple("FileRange").field(file).field(range).finish(),
+            Self::Multipart(file, parts, _) => f.debug_tuple("Multipart").field(file).field(&parts.len()).finish(),
+            Self::Str(str) => f.debug_tuple("Str").field(str).finish(),
+            Self::String(string) => f.debug_tuple("String").field(string).finish(),
+            Self::Buffer(buffer) => f.debug_tuple("Buffer").field(&buffer.len()).finish(),
+            Self::Stream(_) => f.write_str("Stream"),
+            Self::Iter(_) => f.write_str("Iter"),
+            Self::None => f.write_str("None"),
+        }
+    }
+}
+
 fn file_size(file: &File) -> Option<u64> {
     file.metadata().ok().and_then(|file_info| (!file_info.is_dir()).then_some(file_info.len()))
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::net::UnixStream;
+
+    use crate::http::body::{self, Framing};
+    use crate::net::SocketStream;
+
+    fn send(response: Response) -> String {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let mut stream = Stream::from(SocketStream::Unix(local));
+        response.send(&mut stream, None).unwrap();
+        drop(stream);
+        let mut output = String::new();
+        remote.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    fn header() -> String {
+        format!("HTTP/1.1 200 Ok\r\nServer: Rusty HTTP Server {PKG_VERSION}\r\n")
+    }
+
+    #[test]
+    fn send_chunks_with_trailers() {
+        let response = Response::from_chunks(StatusCode::Ok, ["Hello", "", "World!"], None)
+            .with_trailer("X-Checksum", "abc")
+            .with_trailer("X-Count", 2);
+        assert_eq!(send(response), format!("{}Transfer-Encoding: chunked\r\nTrailer: X-Checksum, X-Count\r\n\r\n5\r\nHello\r\n6\r\nWorld!\r\n0\r\nX-Checksum: abc\r\nX-Count: 2\r\n\r\n", header()));
+    }
+
+    #[test]
+    fn send_chunks_from_reader() {
+        let response = Response::from_reader(StatusCode::Ok, Cursor::new(vec![b'x'; 4100]), None);
+        assert_eq!(send(response), format!("{}Transfer-Encoding: chunked\r\n\r\n1000\r\n{}\r\n4\r\nxxxx\r\n0\r\n\r\n", header(), "x".repeat(4096)));
+    }
+
+    #[test]
+    fn send_chunks_without_chunking() {
+        let response = Response::from_chunks(StatusCode::Ok, ["Hello", "World!"], None)
+            .with_trailer("X-Checksum", "abc")
+            .without_chunking();
+        assert_eq!(send(response), format!("{}\r\nHelloWorld!", header()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Trailers require a chunked response!")]
+    fn reject_trailers_without_chunking() {
+        drop(Response::from_text(StatusCode::Ok, "Hello", None).with_trailer("X-Checksum", "abc"));
+    }
+
+    #[test]
+    fn send_chunks_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut stream = Stream::from(SocketStream::Tcp(listener.accept().unwrap().0));
+        let chunks = (0..100).map(|index| format!("line {index}\n"));
+        let expected: String = chunks.clone().collect();
+        Response::from_chunks(StatusCode::Ok, chunks, Some(ContentType::Text)).with_trailer("X-Lines", 100).send(&mut stream, None).unwrap();
+        drop(stream);
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).unwrap();
+        let length = output.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+        let header = std::str::from_utf8(&output[..length]).unwrap();
+        assert!(header.contains("\r\nTransfer-Encoding: chunked\r\nTrailer: X-Lines\r\n"));
+        assert!(!header.contains("Content-Length"));
+        assert!(output.ends_with(b"\r\n0\r\nX-Lines: 100\r\n\r\n"));
+
+        let mut stream = Stream::from(SocketStream::Tcp(client));
+        let (body, remainder) = body::read_body(&mut stream, &output[length..], Framing::Chunked, 1 << 20, None).unwrap();
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+        assert_eq!(remainder.consumed(), output.len() - length);
+    }
+}
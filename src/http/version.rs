@@ -0,0 +1,34 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Version {
+    HTTP10,
+    HTTP11,
+}
+
+impl Version {
+    pub fn from_numbers(major: u32, minor: u32) -> Option<Self> {
+        match (major, minor) {
+            (1, 0) => Some(Self::HTTP10),
+            (1, 1) => Some(Self::HTTP11),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HTTP10 => "HTTP/1.0",
+            Self::HTTP11 => "HTTP/1.1",
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,181 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const CONNECTION_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+pub struct Limiter {
+    max_connections: Option<usize>,
+    rate: Option<TokenRate>,
+    prefix_v4: u8,
+    prefix_v6: u8,
+    state: Mutex<State>,
+}
+
+pub struct ClientGuard {
+    limiter: Arc<Limiter>,
+    key: IpAddr,
+}
+
+struct TokenRate {
+    per_second: f64,
+    burst: f64,
+}
+
+struct State {
+    clients: HashMap<IpAddr, Client>,
+    last_sweep: Instant,
+}
+
+struct Client {
+    connections: usize,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    pub fn new(max_connections: Option<usize>, request_rate: f64, request_burst: Option<u32>, prefix_v4: u8, prefix_v6: u8) -> Result<Self, String> {
+        if prefix_v4 > 32 {
+            return Err(format!("Invalid IPv4 client prefix length: /{prefix_v4}"));
+        }
+        if prefix_v6 > 128 {
+            return Err(format!("Invalid IPv6 client prefix length: /{prefix_v6}"));
+        }
+        if !(request_rate.is_finite() && (request_rate >= 0.0)) {
+            return Err(format!("Invalid client request rate: {request_rate}"));
+        }
+        let rate = (request_rate > 0.0).then(|| TokenRate { per_second: request_rate, burst: request_burst.map_or_else(|| request_rate.ceil(), f64::from).max(1.0) });
+        Ok(Self {
+            max_connections,
+            rate,
+            prefix_v4,
+            prefix_v6,
+            state: Mutex::new(State { clients: HashMap::new(), last_sweep: Instant::now() }),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_connections.is_some() || self.rate.is_some()
+    }
+
+    pub fn acquire(self: &Arc<Self>, addr: IpAddr) -> Result<ClientGuard, Duration> {
+        let key = self.key(addr);
+        let mut state = self.state.lock().unwrap();
+        self.sweep(&mut state);
+        let client = self.client(&mut state, key);
+        if self.max_connections.is_some_and(|max_connections| client.connections >= max_connections) {
+            return Err(CONNECTION_RETRY_AFTER);
+        }
+        client.connections += 1;
+        Ok(ClientGuard { limiter: self.clone(), key })
+    }
+
+    pub fn check_request(&self, addr: IpAddr) -> Result<(), Duration> {
+        let Some(rate) = self.rate.as_ref() else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().unwrap();
+        let client = self.client(&mut state, self.key(addr));
+        if client.tokens < 1.0 {
+            return Err(rate.retry_after(client.tokens));
+        }
+        client.tokens -= 1.0;
+        Ok(())
+    }
+
+    fn client<'a>(&self, state: &'a mut State, key: IpAddr) -> &'a mut Client {
+        let now = Instant::now();
+        let burst = self.rate.as_ref().map_or(0.0, |rate| rate.burst);
+        let client = state.clients.entry(key).or_insert(Client { connections: 0, tokens: burst, updated: now });
+        if let Some(rate) = self.rate.as_ref() {
+            client.tokens = (client.tokens + now.duration_since(client.updated).as_secs_f64() * rate.per_second).min(rate.burst);
+        }
+        client.updated = now;
+        client
+    }
+
+    fn sweep(&self, state: &mut State) {
+        if state.last_sweep.elapsed() < SWEEP_INTERVAL {
+            return;
+        }
+        let now = Instant::now();
+        let idle = |client: &Client| match self.rate.as_ref() {
+            Some(rate) => client.tokens + now.duration_since(client.updated).as_secs_f64() * rate.per_second >= rate.burst,
+            None => true,
+        };
+        state.clients.retain(|_, client| (client.connections > 0) || !idle(client));
+        state.last_sweep = now;
+    }
+
+    fn key(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(v4_addr) => IpAddr::V4(Ipv4Addr::from(u32::from(v4_addr) & u32::MAX.checked_shl(32 - u32::from(self.prefix_v4)).unwrap_or(0))),
+            IpAddr::V6(v6_addr) => match v6_addr.to_ipv4_mapped() {
+                Some(v4_addr) => self.key(IpAddr::V4(v4_addr)),
+                None => IpAddr::V6(Ipv6Addr::from(u128::from(v6_addr) & u128::MAX.checked_shl(128 - u32::from(self.prefix_v6)).unwrap_or(0))),
+            },
+        }
+    }
+}
+
+impl TokenRate {
+    fn retry_after(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64((1.0 - tokens) / self.per_second)
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        if let Some(client) = self.limiter.state.lock().unwrap().clients.get_mut(&self.key) {
+            client.connections = client.connections.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(limiter: &Limiter, addr: &str) -> String {
+        limiter.key(addr.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn mask_ipv4_prefix() {
+        let limiter = Limiter::new(None, 0.0, None, 24, 64).unwrap();
+        assert_eq!(key(&limiter, "192.168.1.77"), "192.168.1.0");
+        assert_eq!(key(&limiter, "::ffff:192.168.1.77"), "192.168.1.0");
+        assert_eq!(key(&Limiter::new(None, 0.0, None, 32, 128).unwrap(), "192.168.1.77"), "192.168.1.77");
+        assert_eq!(key(&Limiter::new(None, 0.0, None, 0, 0).unwrap(), "192.168.1.77"), "0.0.0.0");
+    }
+
+    #[test]
+    fn mask_ipv6_prefix() {
+        let limiter = Limiter::new(None, 0.0, None, 24, 64).unwrap();
+        assert_eq!(key(&limiter, "2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::");
+        assert_eq!(key(&Limiter::new(None, 0.0, None, 24, 48).unwrap(), "2001:db8:1:2:3:4:5:6"), "2001:db8:1::");
+        assert_eq!(key(&Limiter::new(None, 0.0, None, 24, 128).unwrap(), "2001:db8:1:2:3:4:5:6"), "2001:db8:1:2:3:4:5:6");
+    }
+
+    #[test]
+    fn reject_invalid_prefix() {
+        assert!(Limiter::new(None, 0.0, None, 33, 64).is_err());
+        assert!(Limiter::new(None, 0.0, None, 24, 129).is_err());
+    }
+
+    #[test]
+    fn share_limits_within_prefix() {
+        let limiter = Arc::new(Limiter::new(Some(1), 0.0, None, 24, 64).unwrap());
+        let guard = limiter.acquire("192.168.1.1".parse().unwrap()).unwrap();
+        assert!(limiter.acquire("192.168.1.2".parse().unwrap()).is_err());
+        assert!(limiter.acquire("192.168.2.1".parse().unwrap()).is_ok());
+        drop(guard);
+        assert!(limiter.acquire("192.168.1.2".parse().unwrap()).is_ok());
+    }
+}
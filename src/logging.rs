@@ -0,0 +1,185 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter as FmtFormatter, Result as FmtResult, Write as FmtWrite};
+use std::io::{Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::thread;
+
+use env_logger::fmt::Formatter;
+use env_logger::{Env, Logger};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde::Deserialize;
+
+use crate::utils::escape_json;
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+static STRUCTURED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Default)]
+struct Context {
+    connection_id: Option<usize>,
+    peer_addr: Option<SocketAddr>,
+    method: Option<String>,
+    path: Option<String>,
+    status: Option<u16>,
+}
+
+pub struct IdPrefix(usize);
+
+pub struct ThreadPrefix;
+
+struct ReloadableLogger {
+    inner: RwLock<Logger>,
+}
+
+pub fn init(level: Option<LevelFilter>, format: LogFormat) -> Result<(), SetLoggerError> {
+    let logger = build(level, format);
+    let max_level = logger.filter();
+    log::set_logger(LOGGER.get_or_init(|| ReloadableLogger { inner: RwLock::new(logger) }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+pub fn reconfigure(level: Option<LevelFilter>, format: LogFormat) {
+    if let Some(instance) = LOGGER.get() {
+        let logger = build(level, format);
+        log::set_max_level(logger.filter());
+        *instance.inner.write().unwrap() = logger;
+    }
+}
+
+pub fn set_connection(connection_id: usize, peer_addr: Option<SocketAddr>) {
+    update_context(|context| *context = Context { connection_id: Some(connection_id), peer_addr, ..Context::default() });
+}
+
+pub fn set_request(method: impl Display, path: &str) {
+    update_context(|context| {
+        context.method = Some(method.to_string());
+        context.path = Some(path.to_owned());
+        context.status = None;
+    });
+}
+
+pub fn set_status(status: u16) {
+    update_context(|context| context.status = Some(status));
+}
+
+pub fn id_prefix(connection_id: usize) -> IdPrefix {
+    IdPrefix(connection_id)
+}
+
+pub fn thread_prefix() -> ThreadPrefix {
+    ThreadPrefix
+}
+
+pub fn clear_connection() {
+    update_context(|context| *context = Context::default());
+}
+
+fn update_context(fn_update: impl FnOnce(&mut Context)) {
+    if STRUCTURED.load(Ordering::Relaxed) {
+        CONTEXT.with(|context| fn_update(&mut context.borrow_mut()));
+    }
+}
+
+fn build(level: Option<LevelFilter>, format: LogFormat) -> Logger {
+    let mut builder = env_logger::Builder::from_env(Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, LevelFilter::Info.as_str()));
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    if format == LogFormat::Json {
+        builder.format(format_json);
+    }
+    STRUCTURED.store(format == LogFormat::Json, Ordering::Relaxed);
+    builder.build()
+}
+
+fn format_json(buf: &mut Formatter, record: &Record) -> IoResult<()> {
+    let mut line = String::with_capacity(256);
+    let current = thread::current();
+    let thread_name = current.name().map_or_else(|| format!("{:?}", current.id()), str::to_owned);
+    write!(line, "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"target\":\"{}\",\"thread\":\"{}\"", buf.timestamp_millis(), record.level(), escape_json(record.target()), escape_json(&thread_name)).unwrap();
+    CONTEXT.with(|context| {
+        let context = context.borrow();
+        if let Some(connection_id) = context.connection_id {
+            write!(line, ",\"connection_id\":\"{connection_id:X}\"").unwrap();
+        }
+        if let Some(peer_addr) = context.peer_addr {
+            write!(line, ",\"peer\":\"{peer_addr}\"").unwrap();
+        }
+        if let Some(method) = context.method.as_deref() {
+            write!(line, ",\"method\":\"{}\"", escape_json(method)).unwrap();
+        }
+        if let Some(path) = context.path.as_deref() {
+            write!(line, ",\"path\":\"{}\"", escape_json(path)).unwrap();
+        }
+        if let Some(status) = context.status {
+            write!(line, ",\"status\":{status}").unwrap();
+        }
+    });
+    let message = record.args().to_string();
+    write!(line, ",\"message\":\"{}\"}}", escape_json(&message)).unwrap();
+    writeln!(buf, "{line}")
+}
+
+impl Display for IdPrefix {
+    fn fmt(&self, f: &mut FmtFormatter<'_>) -> FmtResult {
+        match STRUCTURED.load(Ordering::Relaxed) {
+            true => Ok(()),
+            false => write!(f, "[id:{:X}] ", self.0),
+        }
+    }
+}
+
+impl Display for ThreadPrefix {
+    fn fmt(&self, f: &mut FmtFormatter<'_>) -> FmtResult {
+        match STRUCTURED.load(Ordering::Relaxed) {
+            true => Ok(()),
+            false => write!(f, "[{:?}] ", thread::current().id()),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("invalid log format '{str}', expected 'text' or 'json'")),
+        }
+    }
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.read().unwrap().log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.read().unwrap().flush()
+    }
+}
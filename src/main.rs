@@ -5,18 +5,32 @@
 #![allow(dead_code)]
 #![allow(clippy::upper_case_acronyms)]
 
-use env_logger::Env;
-use log::{info, warn, error, LevelFilter};
+use log::{info, warn, error};
 use std::env;
-use std::net::{IpAddr, Ipv4Addr};
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::server::Server;
-use crate::web::WebHandler;
+#[cfg(unix)]
+use signal_hook::{consts::{SIGHUP, SIGTERM, SIGUSR1}, iterator::Signals};
+#[cfg(unix)]
+use std::thread;
 
+use crate::cli::{Command, Options};
+use crate::config::Config;
+use crate::limiter::Limiter;
+use crate::server::{Canceller, Server, SharedHandler};
+use crate::web::{AccessLog, WebHandler, KeepAlive};
+
+mod cli;
+mod config;
 mod http;
+mod limiter;
+mod logging;
+mod metrics;
+mod net;
 mod server;
 mod utils;
 mod web;
@@ -24,38 +38,173 @@ mod web;
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() {
-    env_logger::init_from_env(Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, LevelFilter::Info.as_str()));
+    let options = match Command::from_args() {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => return print!("{}", Command::help_text()),
+        Ok(Command::Version) => return println!("Rusty HTTP Server {PKG_VERSION}"),
+        Err(error) => exit_with_error(&format!("{error}\nTry '--help' for more information.")),
+    };
+
+    let config_file = options.config_file.clone().or_else(|| env::var_os("HTTP_CONFIG").filter(|str| !str.is_empty()).map(PathBuf::from));
+    let config = load_config(&options, config_file.as_deref()).unwrap_or_else(|error| exit_with_error(&error));
+    if options.check_config {
+        create_limiter(&config).and_then(|limiter| create_handler(&config, &limiter)).unwrap_or_else(|error| exit_with_error(&error));
+        match config_file {
+            Some(path) => println!("Configuration file {:?} is valid.", path),
+            None => println!("Configuration is valid."),
+        }
+        return;
+    }
+
+    logging::init(config.log_level, config.log_format).unwrap_or_else(|error| exit_with_error(&format!("Failed to initialize logger: {error}")));
     info!("Rusty HTTP Server [{}]", PKG_VERSION);
+    if let Some(path) = config_file.as_ref() {
+        info!("Configuration file: {:?}", path);
+    }
+
+    let limiter = create_limiter(&config).unwrap_or_else(|error| exit_with_error(&error));
+    let handler = SharedHandler::new(create_handler(&config, &limiter).unwrap_or_else(|error| exit_with_error(&error)));
+    let mut server = Server::bind(&config.listen_addresses(), config.socket_mode, config.backlog.map(NonZeroUsize::get), config.threads.map(NonZeroUsize::get), Duration::from_millis(config.shutdown_timeout))
+        .map(|server| server.with_queue_limits(Duration::from_millis(config.queue_timeout), duration(config.queue_max_age)))
+        .map(|server| server.with_worker_limits(config.max_threads.map(NonZeroUsize::get), Duration::from_millis(config.thread_idle_timeout.max(1))))
+        .map(|server| server.with_limiter(limiter.clone()))
+        .unwrap_or_else(|error| exit_with_error(&error.to_string()));
+
+    let canceller = Arc::new(server.canceller().unwrap_or_else(|error| exit_with_error(&format!("Failed to create canceller: {error}"))));
+    let interrupt_canceller = canceller.clone();
+    drop(ctrlc::set_handler(move || request_shutdown(&interrupt_canceller)));
 
-    let public_path = env::var("HTTP_PUBLIC_PATH").map_or_else(|_error| default_public_path(), PathBuf::from);
-    let address = env::var("HTTP_BIND_ADDRESS").ok().map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |str| IpAddr::from_str(&str).expect("Failed to parse bind address!"));
-    let port_number = env::var("HTTP_PORT_NUMBER").ok().map_or(8080, |str| str.parse().expect("Failed to parse port number!"));
-    let thread_count = env::var("HTTP_THREADS").ok().map(|str| str.parse().expect("Failed to parse number of threads!"));
-    let io_timeout = env::var("HTTP_TIMEOUT").ok().map_or(15000, |str| str.parse().expect("Failed to parse the timeout value!"));
+    #[cfg(unix)]
+    spawn_signal_handler(options, config_file, config, limiter, handler.clone(), canceller);
 
-    let public_full_path = public_path.canonicalize().ok().and_then(|path| path.is_dir().then_some(path));
-    if public_full_path.is_none() {
-        error!("Public path {:?} does not exist, is not a directory, or is inaccessible!", public_path);
+    notify_service_manager("READY=1");
+    if let Err(error) = server.run(handler) {
+        exit_with_error(&format!("Server has failed: {error}"));
     }
+    info!("Shutting down application. Goodbye!");
+}
 
-    let handler = WebHandler::new(&public_full_path.expect("Public path not found!"), duration(io_timeout)).expect("Failed to create web-handler instance!");
-    let mut server = Server::bind(address, port_number, None, thread_count).expect("Failed to create the server!");
+fn request_shutdown(canceller: &Canceller) {
+    warn!("Server shutdown has been requested!");
+    notify_service_manager("STOPPING=1");
+    if canceller.cancel().is_err() {
+        error!("Failed to cancel the running server!");
+    }
+}
 
-    let canceller = server.canceller().expect("Failed to create canceller!");
-    drop(ctrlc::set_handler(move || {
-        warn!("Server shutdown has been requested!");
-        if canceller.cancel().is_err() {
-            error!("Failed to cancel the running server!");
+fn load_config(options: &Options, config_file: Option<&Path>) -> Result<Config, String> {
+    let mut config = Config::load(config_file).map_err(|error| error.to_string())?;
+    options.apply(&mut config);
+    Ok(config)
+}
+
+fn create_limiter(config: &Config) -> Result<Arc<Limiter>, String> {
+    Limiter::new(config.max_client_connections.map(NonZeroUsize::get), config.client_request_rate, config.client_request_burst.map(NonZeroU32::get), config.client_prefix_v4, config.client_prefix_v6)
+        .map(Arc::new)
+}
+
+fn create_handler(config: &Config, limiter: &Arc<Limiter>) -> Result<WebHandler, String> {
+    let public_path = match config.public_path.clone() {
+        Some(public_path) => public_path,
+        None => default_public_path()?,
+    };
+    let public_full_path = public_path.canonicalize().ok().and_then(|path| path.is_dir().then_some(path))
+        .ok_or_else(|| format!("Public path {:?} does not exist, is not a directory, or is inaccessible!", public_path))?;
+    let keep_alive = duration(config.keep_alive_timeout).map(|timeout| KeepAlive::new(timeout, config.keep_alive_max.get()));
+    let mut handler = WebHandler::new(&public_full_path, duration(config.timeout), keep_alive, config.max_body_size, config.allow_encoded_slashes, config.directory_listing, &config.index_files)
+        .map_err(|error| format!("Failed to create web-handler instance: {error}"))?;
+    if let Some(metrics_path) = config.metrics_path.as_deref() {
+        if !metrics_path.starts_with('/') {
+            return Err(format!("Metrics path {:?} must start with a slash!", metrics_path));
         }
-    }));
+        handler = handler.with_metrics_path(metrics_path);
+    }
+    handler = handler.with_limiter(limiter.clone());
+    match config.access_log.as_ref() {
+        Some(path) => AccessLog::open(path, &config.access_log_format)
+            .map(|access_log| handler.with_access_log(access_log))
+            .map_err(|error| format!("Failed to open access log {:?}: {error}", path)),
+        None => Ok(handler),
+    }
+}
 
-    server.run(handler).unwrap();
-    info!("Shutting down application. Goodbye!");
+#[cfg(unix)]
+fn spawn_signal_handler(options: Options, config_file: Option<PathBuf>, startup_config: Config, limiter: Arc<Limiter>, handler: SharedHandler<WebHandler>, canceller: Arc<Canceller>) {
+    let mut signals = match Signals::new([SIGHUP, SIGTERM, SIGUSR1]) {
+        Ok(signals) => signals,
+        Err(error) => return warn!("Failed to register signal handler: {error}"),
+    };
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGTERM {
+                request_shutdown(&canceller);
+                continue;
+            }
+            if signal == SIGUSR1 {
+                if let Some(access_log) = handler.current().access_log() {
+                    match access_log.reopen() {
+                        Ok(_) => info!("Access log has been reopened."),
+                        Err(error) => error!("Failed to reopen access log: {error}"),
+                    }
+                }
+                continue;
+            }
+            warn!("Configuration reload has been requested!");
+            notify_service_manager("RELOADING=1");
+            match load_config(&options, config_file.as_deref()).and_then(|new_config| create_handler(&new_config, &limiter).map(|new_handler| (new_config, new_handler))) {
+                Ok((new_config, new_handler)) => {
+                    if requires_restart(&startup_config, &new_config) {
+                        warn!("Changes to listen addresses, threads, backlog, shutdown timeout, queue or client limits take effect only after a restart!");
+                    }
+                    logging::reconfigure(new_config.log_level, new_config.log_format);
+                    handler.replace(new_handler);
+                    info!("Configuration has been reloaded.");
+                },
+                Err(error) => error!("Failed to reload configuration, keeping the current one: {error}"),
+            }
+            notify_service_manager("READY=1");
+        }
+    });
+}
+
+#[cfg(unix)]
+fn requires_restart(startup: &Config, updated: &Config) -> bool {
+    (startup.listen_addresses() != updated.listen_addresses())
+        || (startup.socket_mode != updated.socket_mode)
+        || (startup.backlog != updated.backlog)
+        || (startup.threads != updated.threads)
+        || (startup.max_threads != updated.max_threads)
+        || (startup.thread_idle_timeout != updated.thread_idle_timeout)
+        || (startup.shutdown_timeout != updated.shutdown_timeout)
+        || (startup.queue_timeout != updated.queue_timeout)
+        || (startup.queue_max_age != updated.queue_max_age)
+        || (startup.max_client_connections != updated.max_client_connections)
+        || (startup.client_request_rate != updated.client_request_rate)
+        || (startup.client_request_burst != updated.client_request_burst)
+        || (startup.client_prefix_v4 != updated.client_prefix_v4)
+        || (startup.client_prefix_v6 != updated.client_prefix_v6)
+}
+
+fn default_public_path() -> Result<PathBuf, String> {
+    let exe_file = env::current_exe().map_err(|error| format!("Failed to determine executable file path: {error}"))?;
+    exe_file.parent().map(|base_dir| base_dir.join("public")).ok_or_else(|| String::from("Failed to determine base directory!"))
 }
 
-fn default_public_path() -> PathBuf {
-    let exe_file = env::current_exe().expect("Failed to determine executable file path!");
-    exe_file.parent().expect("Failed to determine base directory!").join("public")
+#[cfg(unix)]
+fn notify_service_manager(state: &str) {
+    match net::systemd::notify(state) {
+        Ok(true) => info!("Notified service manager: {state}"),
+        Ok(false) => (),
+        Err(error) => warn!("Failed to notify service manager: {error}"),
+    }
+}
+
+#[cfg(not(unix))]
+fn notify_service_manager(_state: &str) {}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("Error: {message}");
+    process::exit(1)
 }
 
 fn duration(duration: u64) -> Option<Duration> {
@@ -0,0 +1,138 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+static METRICS: Metrics = Metrics::new();
+
+struct Metrics {
+    connections_accepted: AtomicU64,
+    accept_failures: AtomicU64,
+    enqueue_failures: AtomicU64,
+    rejections: Mutex<BTreeMap<&'static str, u64>>,
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    bytes_sent: AtomicU64,
+    duration_buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    duration_count: AtomicU64,
+    duration_sum_micros: AtomicU64,
+    workers_total: AtomicUsize,
+    workers_busy: AtomicUsize,
+    queue_depth: AtomicUsize,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            connections_accepted: AtomicU64::new(0),
+            accept_failures: AtomicU64::new(0),
+            enqueue_failures: AtomicU64::new(0),
+            rejections: Mutex::new(BTreeMap::new()),
+            requests: Mutex::new(BTreeMap::new()),
+            bytes_sent: AtomicU64::new(0),
+            duration_buckets: [const { AtomicU64::new(0) }; DURATION_BUCKETS.len()],
+            duration_count: AtomicU64::new(0),
+            duration_sum_micros: AtomicU64::new(0),
+            workers_total: AtomicUsize::new(0),
+            workers_busy: AtomicUsize::new(0),
+            queue_depth: AtomicUsize::new(0),
+        }
+    }
+}
+
+pub fn connection_accepted() {
+    METRICS.connections_accepted.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn accept_failed() {
+    METRICS.accept_failures.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn enqueue_failed() {
+    METRICS.enqueue_failures.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn connection_rejected(reason: &'static str) {
+    *METRICS.rejections.lock().unwrap().entry(reason).or_insert(0) += 1;
+}
+
+pub fn connection_enqueued() {
+    METRICS.queue_depth.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn connection_dequeued() {
+    METRICS.queue_depth.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn set_workers(count: usize) {
+    METRICS.workers_total.store(count, Ordering::Relaxed);
+}
+
+pub fn worker_busy() {
+    METRICS.workers_busy.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn worker_idle() {
+    METRICS.workers_busy.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn request_completed(method: &str, status: u16, bytes: u64, duration: Duration) {
+    *METRICS.requests.lock().unwrap().entry((method.to_owned(), status)).or_insert(0) += 1;
+    METRICS.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    let seconds = duration.as_secs_f64();
+    for (bucket, bound) in METRICS.duration_buckets.iter().zip(DURATION_BUCKETS.iter()) {
+        if seconds <= *bound {
+            bucket.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    METRICS.duration_count.fetch_add(1, Ordering::Relaxed);
+    METRICS.duration_sum_micros.fetch_add(duration.as_micros().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+pub fn render() -> String {
+    let mut text = String::with_capacity(4096);
+    counter(&mut text, "rusty_httpd_connections_accepted_total", "Total number of accepted connections.", METRICS.connections_accepted.load(Ordering::Relaxed));
+    counter(&mut text, "rusty_httpd_accept_failures_total", "Total number of failed accept operations.", METRICS.accept_failures.load(Ordering::Relaxed));
+    counter(&mut text, "rusty_httpd_enqueue_failures_total", "Total number of connections dropped because the queue was full.", METRICS.enqueue_failures.load(Ordering::Relaxed));
+
+    text.push_str("# HELP rusty_httpd_connections_rejected_total Total number of rejected connections, by reason.\n# TYPE rusty_httpd_connections_rejected_total counter\n");
+    for (reason, count) in METRICS.rejections.lock().unwrap().iter() {
+        writeln!(text, "rusty_httpd_connections_rejected_total{{reason=\"{reason}\"}} {count}").unwrap();
+    }
+
+    text.push_str("# HELP rusty_httpd_requests_total Total number of requests, by method and status code.\n# TYPE rusty_httpd_requests_total counter\n");
+    for ((method, status), count) in METRICS.requests.lock().unwrap().iter() {
+        writeln!(text, "rusty_httpd_requests_total{{method=\"{method}\",status=\"{status}\"}} {count}").unwrap();
+    }
+
+    counter(&mut text, "rusty_httpd_response_bytes_total", "Total number of response body bytes sent.", METRICS.bytes_sent.load(Ordering::Relaxed));
+
+    text.push_str("# HELP rusty_httpd_request_duration_seconds Time taken to serve a request.\n# TYPE rusty_httpd_request_duration_seconds histogram\n");
+    let count = METRICS.duration_count.load(Ordering::Relaxed);
+    for (bucket, bound) in METRICS.duration_buckets.iter().zip(DURATION_BUCKETS.iter()) {
+        writeln!(text, "rusty_httpd_request_duration_seconds_bucket{{le=\"{bound}\"}} {}", bucket.load(Ordering::Relaxed)).unwrap();
+    }
+    writeln!(text, "rusty_httpd_request_duration_seconds_bucket{{le=\"+Inf\"}} {count}").unwrap();
+    writeln!(text, "rusty_httpd_request_duration_seconds_sum {}", METRICS.duration_sum_micros.load(Ordering::Relaxed) as f64 / 1e6).unwrap();
+    writeln!(text, "rusty_httpd_request_duration_seconds_count {count}").unwrap();
+
+    let total = METRICS.workers_total.load(Ordering::Relaxed);
+    let busy = METRICS.workers_busy.load(Ordering::Relaxed).min(total);
+    text.push_str("# HELP rusty_httpd_worker_threads Number of worker threads, by state.\n# TYPE rusty_httpd_worker_threads gauge\n");
+    writeln!(text, "rusty_httpd_worker_threads{{state=\"busy\"}} {busy}").unwrap();
+    writeln!(text, "rusty_httpd_worker_threads{{state=\"idle\"}} {}", total - busy).unwrap();
+
+    text.push_str("# HELP rusty_httpd_queue_depth Number of accepted connections waiting for a worker thread.\n# TYPE rusty_httpd_queue_depth gauge\n");
+    writeln!(text, "rusty_httpd_queue_depth {}", METRICS.queue_depth.load(Ordering::Relaxed)).unwrap();
+    text
+}
+
+fn counter(text: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(text, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}").unwrap();
+}
@@ -0,0 +1,62 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{AddrParseError, IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+const UNIX_PREFIX: &str = "unix:";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = AddrParseError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str.strip_prefix(UNIX_PREFIX) {
+            Some(path) if !path.is_empty() => Ok(Self::Unix(PathBuf::from(path))),
+            _ => str.parse().map(Self::Tcp),
+        }
+    }
+}
+
+impl Display for ListenAddress {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Tcp(address) => write!(f, "{address}"),
+            Self::Unix(path) => write!(f, "{UNIX_PREFIX}{}", path.display()),
+        }
+    }
+}
+
+impl From<SocketAddr> for ListenAddress {
+    fn from(address: SocketAddr) -> Self {
+        Self::Tcp(address)
+    }
+}
+
+impl<'de> Deserialize<'de> for ListenAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let str = String::deserialize(deserializer)?;
+        str.parse().map_err(|_| D::Error::custom(format!("invalid listen address {:?}, expected \"<ip>:<port>\" or \"unix:<path>\"", str)))
+    }
+}
+
+pub fn normalize_addr(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V6(v6_addr) => match v6_addr.ip().to_ipv4_mapped() {
+            Some(v4_addr) => SocketAddr::new(IpAddr::V4(v4_addr), v6_addr.port()),
+            None => address,
+        },
+        _ => address,
+    }
+}
@@ -0,0 +1,15 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+mod address;
+#[cfg(unix)]
+mod socket;
+mod stream;
+#[cfg(unix)]
+pub mod systemd;
+
+pub use address::{ListenAddress, normalize_addr};
+pub use stream::Stream;
+#[cfg(unix)]
+pub use socket::{SocketListener, SocketCanceller, SocketStream};
@@ -0,0 +1,368 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fs;
+use std::mem;
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::net::{Shutdown, SocketAddr, SocketAddrV6, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use mio::{Events, Interest, Poll, Token, Waker};
+use mio::unix::SourceFd;
+use mtcp_rs::TcpError;
+
+use super::{ListenAddress, normalize_addr};
+
+const LISTENER: Token = Token(0);
+const SHUTDOWN: Token = Token(1);
+const LISTEN_BACKLOG: libc::c_int = 1024;
+
+#[derive(Debug)]
+enum Socket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+#[derive(Debug)]
+pub enum SocketStream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+pub struct SocketListener {
+    socket: Socket,
+    owned_path: Option<PathBuf>,
+    poll: Poll,
+    events: Events,
+    canceller: Arc<SocketCanceller>,
+}
+
+pub struct SocketCanceller {
+    waker: Waker,
+    cancelled: AtomicBool,
+}
+
+impl SocketListener {
+    pub fn bind_unix(path: &Path, mode: Option<u32>) -> IoResult<Self> {
+        remove_stale_socket(path)?;
+        let listener = match mode {
+            Some(mode) => {
+                let previous = unsafe { libc::umask(!mode as libc::mode_t & 0o777) };
+                let result = UnixListener::bind(path);
+                unsafe { libc::umask(previous) };
+                result?
+            },
+            None => UnixListener::bind(path)?,
+        };
+        let mut socket_listener = Self::initialize(Socket::Unix(listener)).inspect_err(|_error| drop(fs::remove_file(path)))?;
+        socket_listener.owned_path = Some(path.to_owned());
+        Ok(socket_listener)
+    }
+
+    pub fn bind_dual_stack(addr: SocketAddrV6) -> IoResult<Self> {
+        let listener = unsafe { TcpListener::from_raw_fd(check(libc::socket(libc::AF_INET6, libc::SOCK_STREAM, 0))?) };
+        let fd = listener.as_raw_fd();
+        check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, 0)?;
+        let mut sockaddr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+        sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+        sockaddr.sin6_port = addr.port().to_be();
+        sockaddr.sin6_flowinfo = addr.flowinfo();
+        sockaddr.sin6_addr.s6_addr = addr.ip().octets();
+        sockaddr.sin6_scope_id = addr.scope_id();
+        check(unsafe { libc::bind(fd, (&sockaddr as *const libc::sockaddr_in6).cast(), mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t) })?;
+        check(unsafe { libc::listen(fd, LISTEN_BACKLOG) })?;
+        Self::initialize(Socket::Tcp(listener))
+    }
+
+    pub fn from_raw_fd(fd: RawFd) -> IoResult<Self> {
+        if get_option(fd, libc::SOL_SOCKET, libc::SO_TYPE)? != libc::SOCK_STREAM {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Socket is not a stream socket!"));
+        }
+        if get_option(fd, libc::SOL_SOCKET, libc::SO_ACCEPTCONN)? == 0 {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Socket is not listening!"));
+        }
+        let listener = unsafe { UnixListener::from_raw_fd(fd) };
+        let socket = match listener.local_addr() {
+            Ok(_) => Socket::Unix(listener),
+            Err(_) => Socket::Tcp(unsafe { TcpListener::from_raw_fd(listener.into_raw_fd()) }),
+        };
+        if let Socket::Tcp(listener) = &socket {
+            listener.local_addr()?;
+        }
+        Self::initialize(socket)
+    }
+
+    fn initialize(socket: Socket) -> IoResult<Self> {
+        let poll = Poll::new()?;
+        let fd = match &socket {
+            Socket::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                listener.as_raw_fd()
+            },
+            Socket::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                listener.as_raw_fd()
+            },
+        };
+        poll.registry().register(&mut SourceFd(&fd), LISTENER, Interest::READABLE)?;
+        let waker = Waker::new(poll.registry(), SHUTDOWN)?;
+        Ok(Self {
+            socket,
+            owned_path: None,
+            poll,
+            events: Events::with_capacity(16),
+            canceller: Arc::new(SocketCanceller { waker, cancelled: AtomicBool::new(false) }),
+        })
+    }
+
+    pub fn local_address(&self) -> Option<ListenAddress> {
+        match &self.socket {
+            Socket::Tcp(listener) => listener.local_addr().ok().map(ListenAddress::Tcp),
+            Socket::Unix(listener) => listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(Path::to_owned)).map(ListenAddress::Unix),
+        }
+    }
+
+    pub fn canceller(&self) -> Arc<SocketCanceller> {
+        self.canceller.clone()
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.canceller.cancelled()
+    }
+
+    pub fn accept(&mut self, timeout: Option<Duration>) -> Result<SocketStream, TcpError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if self.cancelled() {
+                return Err(TcpError::Cancelled);
+            }
+            let result = match &self.socket {
+                Socket::Tcp(listener) => listener.accept().map(|(stream, _addr)| SocketStream::Tcp(stream)),
+                Socket::Unix(listener) => listener.accept().map(|(stream, _addr)| SocketStream::Unix(stream)),
+            };
+            match result {
+                Ok(stream) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                },
+                Err(error) => match error.kind() {
+                    ErrorKind::Interrupted => continue,
+                    ErrorKind::WouldBlock => (),
+                    _ => return Err(error.into()),
+                },
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|time| time.is_zero()) {
+                return Err(TcpError::TimedOut);
+            }
+            if let Err(error) = self.poll.poll(&mut self.events, remaining) {
+                if error.kind() != ErrorKind::Interrupted {
+                    return Err(error.into());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SocketListener {
+    fn drop(&mut self) {
+        if let Some(path) = self.owned_path.as_ref() {
+            if let Err(error) = fs::remove_file(path) {
+                warn!("Failed to remove socket file {:?}: {error}", path);
+            }
+        }
+    }
+}
+
+impl SocketCanceller {
+    pub fn cancel(&self) -> IoResult<bool> {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return Ok(false);
+        }
+        self.waker.wake()?;
+        Ok(true)
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+impl SocketStream {
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(stream) => stream.peer_addr().ok().map(normalize_addr),
+            Self::Unix(_) => None,
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            Self::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    pub fn try_clone(&self) -> IoResult<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+        }
+    }
+
+    pub fn read_timeout(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<usize, TcpError> {
+        let timeout = timeout.map(clamp_timeout);
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout)?,
+            Self::Unix(stream) => stream.set_read_timeout(timeout)?,
+        }
+        loop {
+            match self.read(buffer) {
+                Ok(count) => return Ok(count),
+                Err(error) => match error.kind() {
+                    ErrorKind::Interrupted => (),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut => return Err(TcpError::TimedOut),
+                    _ => return Err(error.into()),
+                },
+            }
+        }
+    }
+
+    pub fn write_all_timeout(&mut self, buffer: &[u8], timeout: Option<Duration>) -> Result<(), TcpError> {
+        let timeout = timeout.map(clamp_timeout);
+        match self {
+            Self::Tcp(stream) => stream.set_write_timeout(timeout)?,
+            Self::Unix(stream) => stream.set_write_timeout(timeout)?,
+        }
+        self.write_all(buffer).map_err(|error| match error.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => TcpError::TimedOut,
+            _ => error.into(),
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            Self::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for SocketStream {
+    fn read(&mut self, buffer: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buffer),
+            Self::Unix(stream) => stream.read(buffer),
+        }
+    }
+}
+
+impl Write for SocketStream {
+    fn write(&mut self, buffer: &[u8]) -> IoResult<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buffer),
+            Self::Unix(stream) => stream.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+fn check(result: libc::c_int) -> IoResult<libc::c_int> {
+    match result {
+        result if result < 0 => Err(IoError::last_os_error()),
+        result => Ok(result),
+    }
+}
+
+fn set_option(fd: RawFd, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> IoResult<()> {
+    check(unsafe { libc::setsockopt(fd, level, name, (&value as *const libc::c_int).cast(), mem::size_of::<libc::c_int>() as libc::socklen_t) }).map(drop)
+}
+
+fn get_option(fd: RawFd, level: libc::c_int, name: libc::c_int) -> IoResult<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut length = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    check(unsafe { libc::getsockopt(fd, level, name, (&mut value as *mut libc::c_int).cast(), &mut length) }).map(|_| value)
+}
+
+fn clamp_timeout(timeout: Duration) -> Duration {
+    timeout.max(Duration::from_millis(1))
+}
+
+fn remove_stale_socket(path: &Path) -> IoResult<()> {
+    let file_info = match fs::symlink_metadata(path) {
+        Ok(file_info) => file_info,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if !file_info.file_type().is_socket() {
+        return Err(IoError::new(ErrorKind::AlreadyExists, "Path exists and is not a socket!"));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(IoError::new(ErrorKind::AddrInUse, "Socket is in use by another process!")),
+        Err(error) if error.kind() == ErrorKind::ConnectionRefused => {
+            info!("Removing stale socket file: {:?}", path);
+            fs::remove_file(path)
+        },
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::net::UdpSocket;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn accept_listening_stream_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let socket_listener = SocketListener::from_raw_fd(listener.into_raw_fd()).unwrap();
+        assert_eq!(socket_listener.local_address(), Some(ListenAddress::Tcp(address)));
+    }
+
+    #[test]
+    fn reject_datagram_socket() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let error = SocketListener::from_raw_fd(socket.as_raw_fd()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn reject_socket_that_is_not_listening() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let error = SocketListener::from_raw_fd(stream.as_raw_fd()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn bind_unix_socket_with_mode() {
+        let directory = env::temp_dir().join(format!("rusty_httpd-socket-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for mode in [0o600, 0o666] {
+            let path = directory.join(format!("{mode:o}.sock"));
+            let listener = SocketListener::bind_unix(&path, Some(mode)).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, mode);
+            drop(listener);
+        }
+        drop(fs::remove_dir_all(directory));
+    }
+}
@@ -0,0 +1,168 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::io::Result as IoResult;
+use std::net::{Shutdown, SocketAddr};
+use std::num::NonZeroUsize;
+use std::time::Duration;
+#[cfg(unix)]
+use std::time::Instant;
+
+use mtcp_rs::{TcpError, TcpStream};
+
+use super::normalize_addr;
+
+#[cfg(unix)]
+use super::SocketStream;
+
+#[cfg(unix)]
+const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub struct Stream {
+    inner: Inner,
+    written: u64,
+    mark: u64,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Socket(SocketStream),
+}
+
+impl Stream {
+    fn new(inner: Inner) -> Self {
+        Self { inner, written: 0, mark: 0 }
+    }
+
+    pub fn mark(&mut self) {
+        self.mark = self.written;
+    }
+
+    pub fn written_since_mark(&self) -> u64 {
+        self.written - self.mark
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match &self.inner {
+            Inner::Tcp(stream) => stream.peer_addr().map(normalize_addr),
+            #[cfg(unix)]
+            Inner::Socket(stream) => stream.peer_addr(),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        match &self.inner {
+            Inner::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Inner::Socket(stream) => stream.shutdown(how),
+        }
+    }
+
+    pub fn read_timeout(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> Result<usize, TcpError> {
+        match &mut self.inner {
+            Inner::Tcp(stream) => stream.read_timeout(buffer, timeout),
+            #[cfg(unix)]
+            Inner::Socket(stream) => stream.read_timeout(buffer, timeout),
+        }
+    }
+
+    pub fn read_all_timeout<F>(&mut self, buffer: &mut Vec<u8>, timeout: Option<Duration>, chunk_size: Option<NonZeroUsize>, maximum_length: Option<NonZeroUsize>, fn_complete: F) -> Result<(), TcpError>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        match &mut self.inner {
+            Inner::Tcp(stream) => stream.read_all_timeout(buffer, timeout, chunk_size, maximum_length, fn_complete),
+            #[cfg(unix)]
+            Inner::Socket(_) => self.read_all_chunked(buffer, timeout, chunk_size, maximum_length, fn_complete),
+        }
+    }
+
+    #[cfg(unix)]
+    fn read_all_chunked<F>(&mut self, buffer: &mut Vec<u8>, timeout: Option<Duration>, chunk_size: Option<NonZeroUsize>, maximum_length: Option<NonZeroUsize>, fn_complete: F) -> Result<(), TcpError>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let chunk_size = chunk_size.map_or(DEFAULT_CHUNK_SIZE, NonZeroUsize::get);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let length = buffer.len();
+            if maximum_length.is_some_and(|maximum| length >= maximum.get()) {
+                return Err(TcpError::TooBig);
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                return Err(TcpError::TimedOut);
+            }
+            buffer.resize(length + maximum_length.map_or(chunk_size, |maximum| chunk_size.min(maximum.get() - length)), 0u8);
+            let result = self.read_timeout(&mut buffer[length..], remaining);
+            buffer.truncate(length + *result.as_ref().unwrap_or(&0));
+            match result {
+                Ok(0) => return Err(TcpError::Incomplete),
+                Ok(_) => {
+                    if fn_complete(buffer) {
+                        return Ok(());
+                    }
+                },
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub fn write_all_timeout(&mut self, buffer: &[u8], timeout: Option<Duration>) -> Result<(), TcpError> {
+        let result = match &mut self.inner {
+            Inner::Tcp(stream) => stream.write_all_timeout(buffer, timeout),
+            #[cfg(unix)]
+            Inner::Socket(stream) => stream.write_all_timeout(buffer, timeout),
+        };
+        if result.is_ok() {
+            self.written += buffer.len() as u64;
+        }
+        result
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Self::new(Inner::Tcp(stream))
+    }
+}
+
+#[cfg(unix)]
+impl From<SocketStream> for Stream {
+    fn from(stream: SocketStream) -> Self {
+        Self::new(Inner::Socket(stream))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    #[test]
+    fn read_all_within_total_timeout() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let mut stream = Stream::from(SocketStream::Unix(local));
+        let writer = thread::spawn(move || {
+            for _ in 0..20 {
+                thread::sleep(Duration::from_millis(50));
+                if remote.write_all(b"x").is_err() {
+                    break;
+                }
+            }
+        });
+        let start = Instant::now();
+        let mut buffer = Vec::new();
+        let result = stream.read_all_timeout(&mut buffer, Some(Duration::from_millis(200)), None, None, |_| false);
+        assert!(matches!(result, Err(TcpError::TimedOut)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        drop(stream);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::env;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+
+const LISTEN_FDS_START: RawFd = 3;
+
+pub fn listen_fds() -> IoResult<Vec<RawFd>> {
+    let Some(pid) = env::var("LISTEN_PID").ok() else {
+        return Ok(Vec::new());
+    };
+    if pid.trim().parse::<u32>().map_err(|_| IoError::new(ErrorKind::InvalidInput, "Invalid LISTEN_PID value!"))? != process::id() {
+        return Ok(Vec::new());
+    }
+    let count = env::var("LISTEN_FDS").ok().and_then(|str| str.trim().parse::<RawFd>().ok()).ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "Invalid LISTEN_FDS value!"))?;
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+    Ok((LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count.max(0))).collect())
+}
+
+pub fn notify(state: &str) -> IoResult<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET").filter(|path| !path.is_empty()) else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => send_abstract(&socket, name, state)?,
+        None => socket.send_to(state.as_bytes(), path).map(|_| ())?,
+    };
+    Ok(true)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_abstract(socket: &UnixDatagram, name: &[u8], state: &str) -> IoResult<()> {
+    #[cfg(target_os = "android")]
+    use std::os::android::net::SocketAddrExt;
+    #[cfg(target_os = "linux")]
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let address = SocketAddr::from_abstract_name(name)?;
+    socket.send_to_addr(state.as_bytes(), &address).map(|_| ())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_abstract(_socket: &UnixDatagram, _name: &[u8], _state: &str) -> IoResult<()> {
+    Err(IoError::new(ErrorKind::Unsupported, "Abstract socket addresses are not supported!"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+    fn set_listen_env(pid: &str, fds: &str) {
+        env::set_var("LISTEN_PID", pid);
+        env::set_var("LISTEN_FDS", fds);
+        env::set_var("LISTEN_FDNAMES", "http");
+    }
+
+    fn clear_listen_env() {
+        for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn take_listen_fds() {
+        let _guard = ENVIRONMENT.lock().unwrap();
+        set_listen_env(&process::id().to_string(), "2");
+        assert_eq!(listen_fds().unwrap(), [3, 4]);
+        assert!(env::var_os("LISTEN_PID").is_none() && env::var_os("LISTEN_FDS").is_none() && env::var_os("LISTEN_FDNAMES").is_none());
+        assert!(listen_fds().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ignore_listen_fds_of_other_process() {
+        let _guard = ENVIRONMENT.lock().unwrap();
+        set_listen_env(&process::id().wrapping_add(1).to_string(), "2");
+        assert!(listen_fds().unwrap().is_empty());
+        assert!(env::var_os("LISTEN_FDS").is_some());
+        clear_listen_env();
+    }
+
+    #[test]
+    fn reject_invalid_listen_fds() {
+        let _guard = ENVIRONMENT.lock().unwrap();
+        set_listen_env(&process::id().to_string(), "two");
+        assert_eq!(listen_fds().err().map(|error| error.kind()), Some(ErrorKind::InvalidInput));
+        set_listen_env("self", "2");
+        assert_eq!(listen_fds().err().map(|error| error.kind()), Some(ErrorKind::InvalidInput));
+        clear_listen_env();
+    }
+
+    #[test]
+    fn notify_socket() {
+        let _guard = ENVIRONMENT.lock().unwrap();
+        let path = env::temp_dir().join(format!("rusty_httpd-notify-{}.sock", process::id()));
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        env::set_var("NOTIFY_SOCKET", &path);
+        let result = notify("READY=1");
+        env::remove_var("NOTIFY_SOCKET");
+        drop(std::fs::remove_file(&path));
+        assert!(result.unwrap());
+        let mut buffer = [0u8; 64];
+        let length = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"READY=1");
+    }
+
+    #[test]
+    fn notify_without_socket() {
+        let _guard = ENVIRONMENT.lock().unwrap();
+        env::remove_var("NOTIFY_SOCKET");
+        assert!(!notify("READY=1").unwrap());
+        env::set_var("NOTIFY_SOCKET", "");
+        assert!(!notify("READY=1").unwrap());
+        env::remove_var("NOTIFY_SOCKET");
+    }
+}
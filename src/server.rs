@@ -2,87 +2,473 @@
  * Rusty HTTP Server - simple and scalable HTTP server
  * This is free and unencumbered software released into the public domain.
  */
-use std::io::{Result, Error};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Result, Error, ErrorKind};
 use std::net::{SocketAddr, IpAddr, Ipv4Addr};
 use std::rc::Rc;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::thread;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, RecvTimeoutError, SendTimeoutError, Sender};
 use log::{debug, info, warn, error};
 use mtcp_rs::{TcpManager, TcpCanceller, TcpListener, TcpConnection, TcpStream, TcpError};
 use num_cpus::get as cpu_count;
 
+use crate::{config, logging, metrics};
+use crate::limiter::{ClientGuard, Limiter};
+use crate::net::{ListenAddress, Stream, normalize_addr};
+
+#[cfg(unix)]
+use std::net::{Shutdown, SocketAddrV6};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+#[cfg(unix)]
+use crate::net::{SocketListener, SocketCanceller, SocketStream, systemd};
+
 static CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
 
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(300);
+const FORCE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_THREADS: usize = 1024;
+
 pub trait Handler : Send + Sync {
-    fn handle_request(&self, id: usize, stream: TcpStream) -> Result<()>;
+    fn handle_request(&self, id: usize, listener: &Listener, stream: Stream, state: &ServerState) -> Result<()>;
+    fn reject_request(&self, id: usize, listener: &Listener, stream: Stream, reason: Rejection) -> Result<()>;
+}
+
+#[derive(Clone, Debug)]
+pub struct Listener {
+    index: usize,
+    address: ListenAddress,
+}
+
+pub struct SharedHandler<H: Handler> {
+    current: Arc<RwLock<Arc<H>>>,
+}
+
+#[derive(Clone, Default)]
+pub struct ServerState {
+    draining: Arc<AtomicBool>,
+    queue: Arc<OnceLock<Receiver<Job>>>,
+}
+
+pub struct Canceller {
+    cancellers: Arc<Vec<CancelHandle>>,
+    state: ServerState,
+}
+
+enum CancelHandle {
+    Tcp(TcpCanceller),
+    #[cfg(unix)]
+    Socket(Arc<SocketCanceller>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Rejection {
+    Overloaded,
+    Expired,
+    Shutdown,
+    Limited(Duration),
+}
+
+enum Connection {
+    Tcp(TcpConnection),
+    #[cfg(unix)]
+    Socket(SocketStream),
+}
+
+enum Binding {
+    Tcp(SocketAddr),
+    Unix(PathBuf, Option<u32>),
+    #[cfg(unix)]
+    DualStack(SocketAddrV6),
+    #[cfg(unix)]
+    Inherited(RawFd),
+}
+
+type Ready = Sender<Result<(Arc<Listener>, CancelHandle)>>;
+
+struct Job {
+    listener: Arc<Listener>,
+    connection: Connection,
+    client: Option<ClientGuard>,
+    queued: Instant,
+}
+
+#[derive(Clone)]
+struct Queue {
+    jobs: Sender<Job>,
+    rejected: Sender<(Job, Rejection)>,
+    limiter: Option<Arc<Limiter>>,
+    grow: Sender<()>,
+    timeout: Duration,
+}
+
+struct WorkerPool {
+    min_threads: usize,
+    max_threads: usize,
+    idle_timeout: Duration,
+    size: AtomicUsize,
+    busy: AtomicUsize,
+    cancellers: Mutex<HashMap<ThreadId, TcpCanceller>>,
+    #[cfg(unix)]
+    streams: Mutex<HashMap<ThreadId, SocketStream>>,
+}
+
+struct Acceptor {
+    listener: Arc<Listener>,
+    start: Option<Sender<Queue>>,
+    handle: JoinHandle<()>,
 }
 
 pub struct Server {
-    manager: Rc<TcpManager>,
-    listener: TcpListener,
+    acceptors: Vec<Acceptor>,
+    cancellers: Arc<Vec<CancelHandle>>,
+    state: ServerState,
+    grace_period: Duration,
+    queue_timeout: Duration,
+    max_queue_age: Option<Duration>,
     backlog: usize,
     threads: usize,
+    max_threads: usize,
+    idle_timeout: Duration,
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl Listener {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn address(&self) -> &ListenAddress {
+        &self.address
+    }
+}
+
+impl<H: Handler> SharedHandler<H> {
+    pub fn new(handler: H) -> Self {
+        Self { current: Arc::new(RwLock::new(Arc::new(handler))) }
+    }
+
+    pub fn replace(&self, handler: H) {
+        *self.current.write().unwrap() = Arc::new(handler);
+    }
+
+    pub fn current(&self) -> Arc<H> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl<H: Handler> Clone for SharedHandler<H> {
+    fn clone(&self) -> Self {
+        Self { current: self.current.clone() }
+    }
+}
+
+impl ServerState {
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        !self.is_draining() && self.queue.get().is_some_and(|queue| !queue.is_full())
+    }
+
+    fn begin(&self) -> bool {
+        !self.draining.swap(true, Ordering::AcqRel)
+    }
+}
+
+impl WorkerPool {
+    fn new(min_threads: usize, max_threads: usize, idle_timeout: Duration) -> Self {
+        Self {
+            min_threads,
+            max_threads,
+            idle_timeout,
+            size: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            cancellers: Mutex::new(HashMap::new()),
+            #[cfg(unix)]
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn should_grow(&self, queued: usize) -> bool {
+        let size = self.size.load(Ordering::Relaxed);
+        (size < self.max_threads) && (queued > size.saturating_sub(self.busy.load(Ordering::Relaxed)))
+    }
+
+    fn retire(&self) -> Option<usize> {
+        let size = self.size.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| (size > self.min_threads).then(|| size - 1)).ok()? - 1;
+        self.unregister();
+        metrics::set_workers(size);
+        Some(size)
+    }
+
+    fn remove(&self) {
+        metrics::set_workers(self.size.fetch_sub(1, Ordering::Relaxed) - 1);
+    }
+
+    fn register(&self, manager: &TcpManager) -> Result<()> {
+        self.cancellers.lock().unwrap().insert(thread::current().id(), manager.canceller()?);
+        Ok(())
+    }
+
+    fn unregister(&self) {
+        self.cancellers.lock().unwrap().remove(&thread::current().id());
+    }
+
+    #[cfg(unix)]
+    fn track(&self, connection: &Connection) {
+        if let Connection::Socket(stream) = connection {
+            match stream.try_clone() {
+                Ok(stream) => drop(self.streams.lock().unwrap().insert(thread::current().id(), stream)),
+                Err(error) => warn!("Failed to track connection for cancellation: {error}"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn untrack(&self) {
+        self.streams.lock().unwrap().remove(&thread::current().id());
+    }
+
+    fn cancel_all(&self) {
+        for canceller in self.cancellers.lock().unwrap().values() {
+            if let Err(error) = canceller.cancel() {
+                error!("Failed to cancel worker thread: {:?}", error);
+            }
+        }
+        #[cfg(unix)]
+        for stream in self.streams.lock().unwrap().values() {
+            if let Err(error) = stream.shutdown(Shutdown::Both) {
+                debug!("Failed to shut down connection: {error}");
+            }
+        }
+    }
+}
+
+impl Rejection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Overloaded => "overloaded",
+            Self::Expired => "expired",
+            Self::Shutdown => "shutdown",
+            Self::Limited(_) => "limited",
+        }
+    }
+}
+
+impl Connection {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(connection) => connection.peer_addr().map(normalize_addr),
+            #[cfg(unix)]
+            Self::Socket(connection) => connection.peer_addr(),
+        }
+    }
+}
+
+impl Canceller {
+    pub fn cancel(&self) -> Result<bool> {
+        let mut result = self.state.begin();
+        for canceller in self.cancellers.iter() {
+            result |= match canceller {
+                CancelHandle::Tcp(canceller) => canceller.cancel()?,
+                #[cfg(unix)]
+                CancelHandle::Socket(canceller) => canceller.cancel()?,
+            };
+        }
+        Ok(result)
+    }
 }
 
 impl Server {
-    pub fn bind(bind_addr: IpAddr, port: u16, backlog: Option<usize>, threads: Option<usize>) -> Result<Self> {
+    pub fn bind(addresses: &[ListenAddress], socket_mode: Option<u32>, backlog: Option<usize>, threads: Option<usize>, grace_period: Duration) -> Result<Self> {
         let threads = threads.unwrap_or_else(cpu_count).clamp(2, 64);
         let backlog = backlog.unwrap_or(256).clamp(1, 16384);
-        let manager = TcpManager::instance()?;
-        let listener = TcpListener::bind(&manager, SocketAddr::new(bind_addr, port))?;
+        if addresses.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "No listen address specified!"));
+        }
+
+        let mut acceptors = Vec::with_capacity(addresses.len());
+        let mut cancellers = Vec::with_capacity(addresses.len());
+        let inherited = inherited_bindings()?;
+        let use_inherited = !inherited.is_empty();
+        if use_inherited {
+            info!("Using {} socket(s) passed by the service manager", inherited.len());
+        }
+        for binding in inherited {
+            let (acceptor, canceller) = Self::spawn_acceptor(acceptors.len(), binding)?;
+            acceptors.push(acceptor);
+            cancellers.push(canceller);
+        }
+
+        for address in addresses.iter().filter(|_| !use_inherited) {
+            #[cfg(unix)]
+            if let Some(socket_addr) = dual_stack_addr(address, addresses) {
+                match Self::spawn_acceptor(acceptors.len(), Binding::DualStack(socket_addr)) {
+                    Ok((acceptor, canceller)) => {
+                        acceptors.push(acceptor);
+                        cancellers.push(canceller);
+                        continue;
+                    },
+                    Err(error) if matches!(error.kind(), ErrorKind::AddrInUse | ErrorKind::PermissionDenied) => return Err(error),
+                    Err(error) => debug!("Dual-stack socket is not available ({error}), binding IPv6 and IPv4 separately"),
+                }
+            }
+            let (acceptor, canceller) = match Self::spawn_acceptor(acceptors.len(), Binding::from(address, socket_mode)) {
+                Ok(result) => result,
+                Err(error) if is_ipv6_unspecified(address) && !matches!(error.kind(), ErrorKind::AddrInUse | ErrorKind::PermissionDenied) => {
+                    warn!("IPv6 is not available ({error}), falling back to IPv4 only!");
+                    Self::spawn_acceptor(acceptors.len(), Binding::from(&ipv4_unspecified(address), socket_mode))?
+                },
+                Err(error) => return Err(error),
+            };
+            acceptors.push(acceptor);
+            cancellers.push(canceller);
+            if is_ipv6_unspecified(address) && !addresses.contains(&ipv4_unspecified(address)) {
+                match Self::spawn_acceptor(acceptors.len(), Binding::from(&ipv4_unspecified(address), socket_mode)) {
+                    Ok((acceptor, canceller)) => {
+                        debug!("IPv6 socket {address} is not dual-stack, listening for IPv4 separately");
+                        acceptors.push(acceptor);
+                        cancellers.push(canceller);
+                    },
+                    Err(error) if error.kind() == ErrorKind::AddrInUse => debug!("IPv6 socket {address} is dual-stack"),
+                    Err(error) => warn!("Failed to listen for IPv4 connections: {error}"),
+                }
+            }
+        }
 
         Ok(Self {
-            manager,
-            listener,
+            acceptors,
+            cancellers: Arc::new(cancellers),
+            state: ServerState::default(),
+            grace_period,
+            queue_timeout: Duration::from_millis(config::DEFAULT_QUEUE_TIMEOUT),
+            max_queue_age: None,
             backlog,
             threads,
+            max_threads: threads,
+            idle_timeout: Duration::from_millis(config::DEFAULT_THREAD_IDLE_TIMEOUT),
+            limiter: None,
         })
     }
 
-    pub fn canceller(&self) -> Result<TcpCanceller> {
-        self.manager.canceller()
+    pub fn with_queue_limits(mut self, timeout: Duration, max_age: Option<Duration>) -> Self {
+        self.queue_timeout = timeout;
+        self.max_queue_age = max_age;
+        self
+    }
+
+    pub fn with_worker_limits(mut self, max_threads: Option<usize>, idle_timeout: Duration) -> Self {
+        self.max_threads = max_threads.unwrap_or(self.threads).clamp(self.threads, MAX_THREADS);
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Arc<Limiter>) -> Self {
+        self.limiter = Some(limiter).filter(|limiter| limiter.is_enabled());
+        self
+    }
+
+    fn spawn_acceptor(index: usize, binding: Binding) -> Result<(Acceptor, CancelHandle)> {
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded(1);
+        let (start_tx, start_rx) = crossbeam_channel::bounded(1);
+        let description = match binding {
+            #[cfg(unix)]
+            Binding::Inherited(_) => format!("use {binding}"),
+            _ => format!("bind to {binding}"),
+        };
+        let handle = match binding {
+            Binding::Tcp(socket_addr) => thread::spawn(move || Self::tcp_acceptor_main(index, socket_addr, ready_tx, start_rx)),
+            #[cfg(unix)]
+            binding => thread::spawn(move || Self::socket_acceptor_main(index, binding, ready_tx, start_rx)),
+            #[cfg(not(unix))]
+            Binding::Unix(_, _) => return Err(Error::new(ErrorKind::Unsupported, format!("Unix domain sockets are not supported on this platform: {binding}"))),
+        };
+        match ready_rx.recv().unwrap_or_else(|_| Err(Error::other("Acceptor thread has terminated!"))) {
+            Ok((listener, canceller)) => Ok((Acceptor { listener, start: Some(start_tx), handle }, canceller)),
+            Err(error) => Err(Error::new(error.kind(), format!("Failed to {description}: {error}"))),
+        }
+    }
+
+    pub fn listeners(&self) -> impl Iterator<Item = &Listener> + '_ {
+        self.acceptors.iter().map(|acceptor| acceptor.listener.as_ref())
+    }
+
+    pub fn canceller(&self) -> Result<Canceller> {
+        Ok(Canceller { cancellers: self.cancellers.clone(), state: self.state.clone() })
     }
 
-    pub fn run(&mut self, handler: impl Handler + 'static) -> Result<()>{
-        info!("Server is listening on: {}", self.listener.local_addr().unwrap());
+    pub fn run(&mut self, handler: SharedHandler<impl Handler + 'static>) -> Result<()>{
+        for listener in self.listeners() {
+            info!("Server is listening on: {}", listener.address);
+        }
 
-        let handler = Arc::new(handler);
-        let (channel_tx, channel_rx) = crossbeam_channel::bounded::<TcpConnection>(self.backlog);
+        let (channel_tx, channel_rx) = crossbeam_channel::bounded::<Job>(self.backlog);
+        let (rejected_tx, rejected_rx) = crossbeam_channel::bounded::<(Job, Rejection)>(self.backlog);
+        let (grow_tx, grow_rx) = crossbeam_channel::bounded::<()>(1);
         let mut error: Option<Error> = None;
         let mut thread_handles = Vec::with_capacity(self.threads);
+        let (done_tx, done_rx) = crossbeam_channel::bounded::<()>(0);
+        let pool = Arc::new(WorkerPool::new(self.threads, self.max_threads, self.idle_timeout));
 
+        if pool.max_threads > pool.min_threads {
+            info!("Worker pool size: {} to {} threads", pool.min_threads, pool.max_threads);
+        }
         for _n in 0..self.threads {
-            let thread_receiver = channel_rx.clone();
-            let thread_handler = handler.clone();
-            thread_handles.push(thread::spawn(move || Self::thread_main(thread_receiver, thread_handler)));
+            thread_handles.push(self.spawn_worker(&pool, &channel_rx, &handler, &done_tx));
         }
 
-        while !self.manager.cancelled() {
-            match self.listener.accept(Some(Duration::from_secs(300))) {
-                Ok(connection) => {
-                    debug!("Connection received: {:?} <- {:?}",
-                        connection.local_addr().unwrap_or_else(unknown),
-                        connection.peer_addr().unwrap_or_else(unknown));
-                    if let Err(error) = channel_tx.send_timeout(connection, Duration::from_secs(30)) {
-                        warn!("Failed to enqueue the connection: {:?}", error);
-                    }
-                },
-                Err(error) => {
-                    match error {
-                        TcpError::Cancelled=> error!("Accept operation was cancelled!"),
-                        TcpError::TimedOut => debug!("Accept operation timed out!"),
-                        TcpError::Failed(inner) => error!("Accept operation failed: {:?}", inner),
-                        TcpError::Incomplete | TcpError::TooBig => unreachable!(),
-                    }
-                },
+        let rejector_handler = handler.clone();
+        let rejector_pool = pool.clone();
+        let rejector_done = done_tx.clone();
+        thread_handles.push(thread::spawn(move || {
+            let _done = rejector_done;
+            Self::rejector_main(rejected_rx, rejector_handler, rejector_pool)
+        }));
+
+        for acceptor in self.acceptors.iter_mut() {
+            if let Some(start) = acceptor.start.take() {
+                if start.send(Queue { jobs: channel_tx.clone(), rejected: rejected_tx.clone(), limiter: self.limiter.clone(), grow: grow_tx.clone(), timeout: self.queue_timeout }).is_err() {
+                    error!("Failed to start acceptor for: {}", acceptor.listener.address);
+                }
             }
         }
 
         drop(channel_tx);
+        drop(grow_tx);
+
+        self.state.queue.set(channel_rx.clone()).ok();
+
+        for _signal in grow_rx.iter() {
+            while pool.should_grow(channel_rx.len()) && !self.state.is_draining() {
+                thread_handles.push(self.spawn_worker(&pool, &channel_rx, &handler, &done_tx));
+                info!("Started additional worker thread, pool size is now {}", pool.size.load(Ordering::Relaxed));
+            }
+            for handle in thread_handles.extract_if(.., |handle| handle.is_finished()) {
+                if let Err(err) = handle.join().expect("Failed to join worker thread!") {
+                    error.replace(err);
+                }
+            }
+        }
+
+        drop(done_tx);
+        self.acceptors.drain(..).for_each(|acceptor| acceptor.handle.join().expect("Failed to join acceptor thread!"));
+
+        Self::reject_queued(&channel_rx, &rejected_tx);
+        drop(rejected_tx);
+        drop(channel_rx);
+
+        if !self.drain_workers(&done_rx, &pool) {
+            warn!("Worker threads did not terminate in time, abandoning them!");
+            return Ok(());
+        }
 
         thread_handles.drain(..).for_each(|handle| {
             if let Err(err) = handle.join().expect("Failed to join worker thread!") {
@@ -93,30 +479,309 @@ impl Server {
         error.map(Err).unwrap_or(Ok(()))
     }
 
-    fn thread_main(receiver: Receiver<TcpConnection>, handler: Arc<impl Handler>) -> Result<()> {
-        let manager = TcpManager::instance()?;
-        loop {
-            match receiver.recv() {
+    fn spawn_worker(&self, pool: &Arc<WorkerPool>, receiver: &Receiver<Job>, handler: &SharedHandler<impl Handler + 'static>, done: &Sender<()>) -> JoinHandle<Result<()>> {
+        pool.size.fetch_add(1, Ordering::Relaxed);
+        metrics::set_workers(pool.size.load(Ordering::Relaxed));
+        let thread_receiver = receiver.clone();
+        let thread_handler = handler.clone();
+        let thread_state = self.state.clone();
+        let thread_pool = pool.clone();
+        let thread_done = done.clone();
+        let max_queue_age = self.max_queue_age;
+        thread::spawn(move || {
+            let _done = thread_done;
+            Self::thread_main(thread_receiver, thread_handler, thread_state, max_queue_age, thread_pool)
+        })
+    }
+
+    fn drain_workers(&self, done: &Receiver<()>, pool: &WorkerPool) -> bool {
+        info!("Waiting for pending connections to complete...");
+        if done.recv_timeout(self.grace_period) != Err(RecvTimeoutError::Timeout) {
+            return true;
+        }
+        warn!("Grace period has expired, cancelling the remaining connections!");
+        pool.cancel_all();
+        done.recv_timeout(FORCE_TIMEOUT) != Err(RecvTimeoutError::Timeout)
+    }
+
+    fn tcp_acceptor_main(index: usize, address: SocketAddr, ready: Ready, start: Receiver<Queue>) {
+        let init = || -> Result<_> {
+            let manager = TcpManager::instance()?;
+            let tcp_listener = TcpListener::bind(&manager, address)?;
+            let canceller = manager.canceller()?;
+            Ok((manager, tcp_listener, canceller))
+        };
+        let (manager, tcp_listener, canceller) = match init() {
+            Ok(result) => result,
+            Err(error) => return drop(ready.send(Err(error))),
+        };
+
+        let listener = Arc::new(Listener { index, address: ListenAddress::Tcp(tcp_listener.local_addr().unwrap_or(address)) });
+        let Some(channel_tx) = Self::acceptor_ready(&listener, CancelHandle::Tcp(canceller), ready, start) else {
+            return;
+        };
+
+        while !manager.cancelled() {
+            match tcp_listener.accept(Some(ACCEPT_TIMEOUT)) {
+                Ok(connection) => {
+                    debug!("Connection received: {:?} <- {:?}",
+                        connection.local_addr().unwrap_or_else(unknown),
+                        normalize_addr(connection.peer_addr().unwrap_or_else(unknown)));
+                    Self::enqueue(&channel_tx, &listener, Connection::Tcp(connection));
+                },
+                Err(error) => Self::accept_failed(error),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn socket_acceptor_main(index: usize, binding: Binding, ready: Ready, start: Receiver<Queue>) {
+        let result = match &binding {
+            Binding::Unix(path, mode) => SocketListener::bind_unix(path, *mode),
+            Binding::DualStack(socket_addr) => SocketListener::bind_dual_stack(*socket_addr),
+            Binding::Inherited(fd) => SocketListener::from_raw_fd(*fd),
+            Binding::Tcp(_) => unreachable!(),
+        };
+        let mut socket_listener = match result {
+            Ok(socket_listener) => socket_listener,
+            Err(error) => return drop(ready.send(Err(error))),
+        };
+
+        let address = match binding {
+            Binding::Unix(path, _) => ListenAddress::Unix(path),
+            _ => socket_listener.local_address().unwrap_or_else(|| ListenAddress::Unix(PathBuf::new())),
+        };
+        let listener = Arc::new(Listener { index, address });
+        let Some(channel_tx) = Self::acceptor_ready(&listener, CancelHandle::Socket(socket_listener.canceller()), ready, start) else {
+            return;
+        };
+
+        while !socket_listener.cancelled() {
+            match socket_listener.accept(Some(ACCEPT_TIMEOUT)) {
                 Ok(connection) => {
+                    debug!("Connection received: {:?} <- {:?}", listener.address, connection.peer_addr());
+                    Self::enqueue(&channel_tx, &listener, Connection::Socket(connection));
+                },
+                Err(error) => Self::accept_failed(error),
+            }
+        }
+    }
+
+    fn acceptor_ready(listener: &Arc<Listener>, canceller: CancelHandle, ready: Ready, start: Receiver<Queue>) -> Option<Queue> {
+        ready.send(Ok((listener.clone(), canceller))).ok()?;
+        start.recv().ok()
+    }
+
+    fn enqueue(queue: &Queue, listener: &Arc<Listener>, connection: Connection) {
+        metrics::connection_accepted();
+        let client = match queue.limiter.as_ref().zip(connection.peer_addr()) {
+            Some((limiter, peer_addr)) => match limiter.acquire(peer_addr.ip()) {
+                Ok(guard) => Some(guard),
+                Err(retry_after) => {
+                    debug!("Client {} has exceeded its limits, rejecting the connection!", peer_addr.ip());
+                    return Self::enqueue_rejection(queue, Job { listener: listener.clone(), connection, client: None, queued: Instant::now() }, Rejection::Limited(retry_after));
+                },
+            },
+            None => None,
+        };
+        metrics::connection_enqueued();
+        Self::request_growth(queue);
+        match queue.jobs.send_timeout(Job { listener: listener.clone(), connection, client, queued: Instant::now() }, queue.timeout) {
+            Ok(_) => Self::request_growth(queue),
+            Err(SendTimeoutError::Timeout(job)) => {
+                metrics::connection_dequeued();
+                debug!("All worker threads are busy, rejecting the connection!");
+                Self::enqueue_rejection(queue, job, Rejection::Overloaded);
+            },
+            Err(error) => {
+                warn!("Failed to enqueue the connection: {:?}", error);
+                metrics::connection_dequeued();
+                metrics::enqueue_failed();
+            },
+        }
+    }
+
+    fn reject_queued(jobs: &Receiver<Job>, rejected: &Sender<(Job, Rejection)>) {
+        let mut count = 0usize;
+        for job in jobs.try_iter() {
+            metrics::connection_dequeued();
+            match rejected.send((job, Rejection::Shutdown)) {
+                Ok(_) => count += 1,
+                Err(_) => metrics::enqueue_failed(),
+            }
+        }
+        if count > 0 {
+            info!("Rejecting {count} queued connection(s) on shutdown");
+        }
+    }
+
+    fn enqueue_rejection(queue: &Queue, job: Job, reason: Rejection) {
+        if let Err(error) = queue.rejected.try_send((job, reason)) {
+            warn!("Failed to enqueue the connection for rejection: {:?}", error);
+            metrics::enqueue_failed();
+        }
+    }
+
+    fn request_growth(queue: &Queue) {
+        if !queue.jobs.is_empty() {
+            queue.grow.try_send(()).ok();
+        }
+    }
+
+    fn accept_failed(error: TcpError) {
+        match error {
+            TcpError::Cancelled=> error!("Accept operation was cancelled!"),
+            TcpError::TimedOut => debug!("Accept operation timed out!"),
+            TcpError::Failed(inner) => {
+                error!("Accept operation failed: {:?}", inner);
+                metrics::accept_failed();
+            },
+            TcpError::Incomplete | TcpError::TooBig => unreachable!(),
+        }
+    }
+
+    fn thread_main(receiver: Receiver<Job>, handler: SharedHandler<impl Handler>, state: ServerState, max_queue_age: Option<Duration>, pool: Arc<WorkerPool>) -> Result<()> {
+        let manager = match TcpManager::instance().and_then(|manager| pool.register(&manager).map(|_| manager)) {
+            Ok(manager) => manager,
+            Err(error) => {
+                pool.remove();
+                return Err(error);
+            },
+        };
+        loop {
+            match receiver.recv_timeout(pool.idle_timeout) {
+                Ok(job) => {
+                    metrics::connection_dequeued();
+                    metrics::worker_busy();
+                    pool.busy.fetch_add(1, Ordering::Relaxed);
                     let id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
-                    info!("[id:{id:X}] [{:?}] Received connection from: {}", thread::current().id(), connection.local_addr().unwrap_or_else(unknown));
-                    match TcpStream::from(&manager, connection) {
+                    logging::set_connection(id, None);
+                    let handler = handler.current();
+                    let expired = max_queue_age.is_some_and(|max_age| job.queued.elapsed() > max_age);
+                    #[cfg(unix)]
+                    pool.track(&job.connection);
+                    match Self::open_stream(&manager, job.connection) {
+                        Ok(stream) if state.is_draining() => Self::reject(handler.as_ref(), id, &job.listener, stream, Rejection::Shutdown),
+                        Ok(stream) if expired => Self::reject(handler.as_ref(), id, &job.listener, stream, Rejection::Expired),
                         Ok(stream) => {
-                            if let Err(err) = handler.handle_request(id, stream) {
-                                error!("[id:{id:X}] The request has failed: {:?}", err);
+                            logging::set_connection(id, stream.peer_addr());
+                            info!("{}{}Received connection from: {} on {}", logging::id_prefix(id), logging::thread_prefix(), peer_name(&stream), job.listener.address);
+                            if let Err(err) = handler.handle_request(id, &job.listener, stream, &state) {
+                                error!("{}The request has failed: {:?}", logging::id_prefix(id), err);
                             }
                         },
-                        Err(err) => error!("[id:{id:X}] Failed to initialize stream: {:?}", err),
+                        Err(err) => error!("{}Failed to initialize stream: {:?}", logging::id_prefix(id), err),
+                    }
+                    #[cfg(unix)]
+                    pool.untrack();
+                    logging::clear_connection();
+                    pool.busy.fetch_sub(1, Ordering::Relaxed);
+                    metrics::worker_idle();
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(size) = pool.retire() {
+                        info!("{}Retiring idle worker thread, pool size is now {size}", logging::thread_prefix());
+                        return Ok(());
                     }
                 },
-                Err(_) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    pool.unregister();
+                    return Ok(());
+                },
             };
         };
     }
+
+    fn rejector_main(receiver: Receiver<(Job, Rejection)>, handler: SharedHandler<impl Handler>, pool: Arc<WorkerPool>) -> Result<()> {
+        let manager = TcpManager::instance()?;
+        pool.register(&manager)?;
+        for (job, reason) in receiver.iter() {
+            let id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+            logging::set_connection(id, None);
+            match Self::open_stream(&manager, job.connection) {
+                Ok(stream) => Self::reject(handler.current().as_ref(), id, &job.listener, stream, reason),
+                Err(err) => error!("{}Failed to initialize stream: {:?}", logging::id_prefix(id), err),
+            }
+            logging::clear_connection();
+        }
+        pool.unregister();
+        Ok(())
+    }
+
+    fn open_stream(manager: &Rc<TcpManager>, connection: Connection) -> Result<Stream> {
+        match connection {
+            Connection::Tcp(connection) => TcpStream::from(manager, connection).map(Stream::from),
+            #[cfg(unix)]
+            Connection::Socket(connection) => Ok(Stream::from(connection)),
+        }
+    }
+
+    fn reject(handler: &impl Handler, id: usize, listener: &Listener, stream: Stream, reason: Rejection) {
+        logging::set_connection(id, stream.peer_addr());
+        info!("{}{}Rejecting connection from: {} on {} ({})", logging::id_prefix(id), logging::thread_prefix(), peer_name(&stream), listener.address, reason.as_str());
+        metrics::connection_rejected(reason.as_str());
+        if let Err(err) = handler.reject_request(id, listener, stream, reason) {
+            warn!("{}Failed to reject the connection: {:?}", logging::id_prefix(id), err);
+        }
+    }
 }
 
-fn unknown() -> SocketAddr {
-    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+impl Binding {
+    fn from(address: &ListenAddress, socket_mode: Option<u32>) -> Self {
+        match address {
+            ListenAddress::Tcp(socket_addr) => Self::Tcp(*socket_addr),
+            ListenAddress::Unix(path) => Self::Unix(path.clone(), socket_mode),
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Tcp(socket_addr) => write!(f, "{socket_addr}"),
+            Self::Unix(path, _) => write!(f, "{}", ListenAddress::Unix(path.clone())),
+            #[cfg(unix)]
+            Self::DualStack(socket_addr) => write!(f, "{socket_addr}"),
+            #[cfg(unix)]
+            Self::Inherited(fd) => write!(f, "inherited socket (fd {fd})"),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inherited_bindings() -> Result<Vec<Binding>> {
+    systemd::listen_fds().map(|fds| fds.into_iter().map(Binding::Inherited).collect())
+}
+
+#[cfg(not(unix))]
+fn inherited_bindings() -> Result<Vec<Binding>> {
+    Ok(Vec::new())
+}
+
+fn peer_name(stream: &Stream) -> String {
+    stream.peer_addr().map_or_else(|| String::from("local socket"), |address| address.to_string())
+}
+
+fn is_ipv6_unspecified(address: &ListenAddress) -> bool {
+    matches!(address, ListenAddress::Tcp(SocketAddr::V6(address)) if address.ip().is_unspecified() && (address.port() != 0))
 }
 
+#[cfg(unix)]
+fn dual_stack_addr(address: &ListenAddress, addresses: &[ListenAddress]) -> Option<SocketAddrV6> {
+    match address {
+        ListenAddress::Tcp(SocketAddr::V6(socket_addr)) if is_ipv6_unspecified(address) && !addresses.contains(&ipv4_unspecified(address)) => Some(*socket_addr),
+        _ => None,
+    }
+}
+
+fn ipv4_unspecified(address: &ListenAddress) -> ListenAddress {
+    let port = match address {
+        ListenAddress::Tcp(address) => address.port(),
+        ListenAddress::Unix(_) => 0,
+    };
+    ListenAddress::Tcp(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))
+}
 
+fn unknown() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+}
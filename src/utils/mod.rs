@@ -2,6 +2,8 @@
  * Rusty HTTP Server - simple and scalable HTTP server
  * This is free and unencumbered software released into the public domain.
  */
+mod json;
 mod value;
 
-pub use value::{Value, ValueMap};
+pub use json::escape_json;
+pub use value::ValueMap;
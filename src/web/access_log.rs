@@ -0,0 +1,317 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::fmt::Write as FmtWrite;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Error as IoError, ErrorKind, LineWriter, Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
+use crate::http::{Request, StatusCode};
+
+const COMMON_FORMAT: &str = "%h %l %u %t \"%r\" %>s %b";
+const COMBINED_FORMAT: &str = "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-Agent}i\"";
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+#[derive(Debug)]
+enum Token {
+    Literal(String),
+    RemoteAddr,
+    Unknown,
+    Time,
+    RequestLine,
+    Method,
+    Path,
+    Query,
+    Protocol,
+    Status,
+    BytesClf,
+    Bytes,
+    Header(String),
+    Micros,
+    Seconds,
+}
+
+enum Output {
+    Stdout,
+    File(PathBuf, LineWriter<File>),
+}
+
+pub struct AccessLog {
+    tokens: Vec<Token>,
+    output: Mutex<Output>,
+}
+
+pub struct Entry<'a> {
+    pub peer_addr: Option<SocketAddr>,
+    pub time: SystemTime,
+    pub request_line: Option<&'a str>,
+    pub request: Option<&'a Request<'a>>,
+    pub status: StatusCode,
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+impl AccessLog {
+    pub fn open(path: &Path, format: &str) -> IoResult<Self> {
+        let tokens = parse_format(format).map_err(|error| IoError::new(ErrorKind::InvalidInput, error))?;
+        let output = match path.as_os_str() == "-" {
+            true => Output::Stdout,
+            false => Output::File(path.to_owned(), open_file(path)?),
+        };
+        Ok(Self { tokens, output: Mutex::new(output) })
+    }
+
+    pub fn reopen(&self) -> IoResult<()> {
+        let mut output = self.output.lock().unwrap();
+        if let Output::File(path, writer) = &mut *output {
+            *writer = open_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, entry: &Entry) {
+        let mut line = String::with_capacity(256);
+        for token in self.tokens.iter() {
+            entry.format(&mut line, token);
+        }
+        line.push('\n');
+        let result = match &mut *self.output.lock().unwrap() {
+            Output::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            Output::File(_, writer) => writer.write_all(line.as_bytes()),
+        };
+        if let Err(error) = result {
+            warn!("Failed to write access log: {error}");
+        }
+    }
+}
+
+impl<'a> Entry<'a> {
+    fn format(&self, line: &mut String, token: &Token) {
+        match token {
+            Token::Literal(text) => line.push_str(text),
+            Token::RemoteAddr => match self.peer_addr {
+                Some(addr) => write!(line, "{}", addr.ip()).unwrap(),
+                None => line.push('-'),
+            },
+            Token::Unknown => line.push('-'),
+            Token::Time => format_time(line, self.time),
+            Token::RequestLine => push_escaped(line, self.request_line),
+            Token::Method => push_escaped(line, self.request.map(|request| request.method().to_string()).as_deref()),
+            Token::Path => push_escaped(line, self.request.map(Request::path)),
+            Token::Query => {
+                if let Some(query) = self.request.and_then(|request| request.target().get(request.path().len()..)) {
+                    escape_into(line, query);
+                }
+            },
+            Token::Protocol => push_escaped(line, self.request.map(|request| request.version().as_str())),
+            Token::Status => write!(line, "{}", self.status).unwrap(),
+            Token::BytesClf => match self.bytes {
+                0 => line.push('-'),
+                bytes => write!(line, "{bytes}").unwrap(),
+            },
+            Token::Bytes => write!(line, "{}", self.bytes).unwrap(),
+            Token::Header(name) => push_escaped(line, self.request.and_then(|request| request.header(name))),
+            Token::Micros => write!(line, "{}", self.duration.as_micros()).unwrap(),
+            Token::Seconds => write!(line, "{}", self.duration.as_secs()).unwrap(),
+        }
+    }
+}
+
+fn open_file(path: &Path) -> IoResult<LineWriter<File>> {
+    OpenOptions::new().create(true).append(true).open(path).map(LineWriter::new)
+}
+
+fn parse_format(format: &str) -> Result<Vec<Token>, String> {
+    let format = match format {
+        "common" => COMMON_FORMAT,
+        "combined" => COMBINED_FORMAT,
+        other => other,
+    };
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        let mut directive = chars.next().ok_or("Incomplete directive at end of access log format!")?;
+        if directive == '%' {
+            literal.push('%');
+            continue;
+        }
+        let mut argument = None;
+        if directive == '{' {
+            argument = Some(chars.by_ref().take_while(|&c| c != '}').collect::<String>());
+            directive = chars.next().ok_or("Incomplete directive at end of access log format!")?;
+        } else if directive == '>' {
+            directive = chars.next().ok_or("Incomplete directive at end of access log format!")?;
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        tokens.push(match (directive, argument) {
+            ('h' | 'a', None) => Token::RemoteAddr,
+            ('l' | 'u', None) => Token::Unknown,
+            ('t', None) => Token::Time,
+            ('r', None) => Token::RequestLine,
+            ('m', None) => Token::Method,
+            ('U', None) => Token::Path,
+            ('q', None) => Token::Query,
+            ('H', None) => Token::Protocol,
+            ('s', None) => Token::Status,
+            ('b', None) => Token::BytesClf,
+            ('B', None) => Token::Bytes,
+            ('D', None) => Token::Micros,
+            ('T', None) => Token::Seconds,
+            ('i', Some(name)) if !name.is_empty() => Token::Header(name),
+            (other, _) => return Err(format!("Unsupported access log directive: %{other}")),
+        });
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+fn format_time(line: &mut String, time: SystemTime) {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    write!(line, "[{:02}/{}/{}:{:02}:{:02}:{:02} +0000]", day, MONTHS[(month - 1) as usize], year, secs_of_day / 3600, (secs_of_day / 60) % 60, secs_of_day % 60).unwrap();
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn push_escaped(line: &mut String, value: Option<&str>) {
+    match value.filter(|value| !value.is_empty()) {
+        Some(value) => escape_into(line, value),
+        None => line.push('-'),
+    }
+}
+
+fn escape_into(line: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            c if c.is_control() => write!(line, "\\x{:02x}", c as u32).unwrap(),
+            c => line.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = "GET /index.html?x=1&y=2 HTTP/1.1\r\nReferer: http://example.com/\r\nUser-Agent: say \"hi\"\\\r\n\r\n";
+
+    fn render(format: &str, entry: &Entry) -> String {
+        let mut line = String::new();
+        for token in parse_format(format).unwrap().iter() {
+            entry.format(&mut line, token);
+        }
+        line
+    }
+
+    fn render_request(format: &str) -> String {
+        let (request, _) = Request::parse(REQUEST.as_bytes()).unwrap();
+        render(format, &Entry {
+            peer_addr: Some("192.0.2.1:1234".parse().unwrap()),
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            request_line: REQUEST.lines().next(),
+            request: Some(&request),
+            status: StatusCode::Ok,
+            bytes: 1234,
+            duration: Duration::from_micros(2_500_000),
+        })
+    }
+
+    fn error(format: &str) -> String {
+        parse_format(format).err().unwrap()
+    }
+
+    #[test]
+    fn format_directives() {
+        assert_eq!(render_request("%h %a %l %u %m %U %q %H %s %>s %b %B %D %T"), "192.0.2.1 192.0.2.1 - - GET /index.html ?x=1&y=2 HTTP/1.1 200 200 1234 1234 2500000 2");
+        assert_eq!(render_request("%t"), "[14/Nov/2023:22:13:20 +0000]");
+        assert_eq!(render_request("\"%r\""), "\"GET /index.html?x=1&y=2 HTTP/1.1\"");
+        assert_eq!(render_request("%{Referer}i|%{user-agent}i|%{X-Missing}i"), "http://example.com/|say \\\"hi\\\"\\\\|-");
+    }
+
+    #[test]
+    fn format_without_request() {
+        let entry = Entry { peer_addr: None, time: UNIX_EPOCH, request_line: None, request: None, status: StatusCode::BadRequest, bytes: 0, duration: Duration::ZERO };
+        assert_eq!(render("%h %t \"%r\" %m %U%q %H %s %b %B %{Referer}i", &entry), "- [01/Jan/1970:00:00:00 +0000] \"-\" - - - 400 - 0 -");
+    }
+
+    #[test]
+    fn named_formats() {
+        assert_eq!(render_request("common"), "192.0.2.1 - - [14/Nov/2023:22:13:20 +0000] \"GET /index.html?x=1&y=2 HTTP/1.1\" 200 1234");
+        assert_eq!(render_request("combined"), "192.0.2.1 - - [14/Nov/2023:22:13:20 +0000] \"GET /index.html?x=1&y=2 HTTP/1.1\" 200 1234 \"http://example.com/\" \"say \\\"hi\\\"\\\\\"");
+    }
+
+    #[test]
+    fn escape_percent() {
+        assert_eq!(render_request("100%% %s"), "100% 200");
+        assert_eq!(render_request("%%%s%%"), "%200%");
+        assert_eq!(render_request("%%h"), "%h");
+    }
+
+    #[test]
+    fn reject_unknown_directives() {
+        assert_eq!(error("%Z"), "Unsupported access log directive: %Z");
+        assert_eq!(error("%i"), "Unsupported access log directive: %i");
+        assert_eq!(error("%{}i"), "Unsupported access log directive: %i");
+        assert_eq!(error("%{Referer}s"), "Unsupported access log directive: %s");
+        assert_eq!(error("%s %"), "Incomplete directive at end of access log format!");
+        assert_eq!(error("%{Referer"), "Incomplete directive at end of access log format!");
+    }
+
+    #[test]
+    fn convert_days_around_epoch() {
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(364), (1970, 12, 31));
+        assert_eq!(civil_from_days(365), (1971, 1, 1));
+    }
+
+    #[test]
+    fn convert_days_around_year_boundaries() {
+        assert_eq!(civil_from_days(10956), (1999, 12, 31));
+        assert_eq!(civil_from_days(10957), (2000, 1, 1));
+    }
+
+    #[test]
+    fn convert_days_around_leap_days() {
+        assert_eq!(civil_from_days(11015), (2000, 2, 28));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19416), (2023, 2, 28));
+        assert_eq!(civil_from_days(19417), (2023, 3, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-25509), (1900, 2, 28));
+        assert_eq!(civil_from_days(-25508), (1900, 3, 1));
+        assert_eq!(civil_from_days(47540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47541), (2100, 3, 1));
+        assert_eq!(civil_from_days(-135081), (1600, 2, 29));
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * Rusty HTTP Server - simple and scalable HTTP server
+ * This is free and unencumbered software released into the public domain.
+ */
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use httpdate::HttpDate;
+use urlencoding::encode as url_encode;
+
+use crate::utils::escape_json;
+
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug)]
+pub struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    pub fn parse(str: &str) -> Option<Self> {
+        match str {
+            "name" => Some(Self::Name),
+            "size" => Some(Self::Size),
+            "modified" => Some(Self::Modified),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Size => "size",
+            Self::Modified => "modified",
+        }
+    }
+}
+
+pub fn read_entries(directory: &Path, filter: impl Fn(&OsStr) -> bool) -> IoResult<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for dir_entry in fs::read_dir(directory)?.filter_map(Result::ok) {
+        let file_name = dir_entry.file_name();
+        if !filter(&file_name) {
+            continue;
+        }
+        if let (Some(name), Ok(file_info)) = (file_name.to_str(), fs::metadata(dir_entry.path())) {
+            entries.push(Entry {
+                name: name.to_owned(),
+                is_dir: file_info.is_dir(),
+                size: if file_info.is_dir() { 0 } else { file_info.len() },
+                modified: file_info.modified().ok(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+pub fn sort_entries(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }.then_with(|| a.name.cmp(&b.name));
+        b.is_dir.cmp(&a.is_dir).then(if descending { ordering.reverse() } else { ordering })
+    });
+}
+
+pub fn render_html(path: &str, entries: &[Entry], key: SortKey, descending: bool) -> String {
+    let title = escape_html(path);
+    let mut html = String::with_capacity(1024 + 256 * entries.len());
+    write!(html, "<!doctype html><html><head><meta charset=\"utf-8\"><title>Index of {title}</title><style>td,th{{padding:0 1em;text-align:left}}</style></head>").unwrap();
+    write!(html, "<body><h1>Index of {title}</h1><table><tr>").unwrap();
+    for (column, label) in [(SortKey::Name, "Name"), (SortKey::Size, "Size"), (SortKey::Modified, "Last Modified")] {
+        let order = if (column == key) && !descending { "desc" } else { "asc" };
+        write!(html, "<th><a href=\"?sort={}&amp;order={order}\">{label}</a></th>", column.as_str()).unwrap();
+    }
+    html.push_str("</tr>");
+    if path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td>-</td><td>-</td></tr>");
+    }
+    for entry in entries {
+        let (suffix, size) = match entry.is_dir {
+            true => ("/", String::from("-")),
+            false => ("", entry.size.to_string()),
+        };
+        let modified = entry.modified.map_or_else(|| String::from("-"), |time| HttpDate::from(time).to_string());
+        write!(html, "<tr><td><a href=\"{}{suffix}\">{}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>", url_encode(&entry.name), escape_html(&entry.name)).unwrap();
+    }
+    writeln!(html, "</table><hr><p>Rusty HTTP Server {PKG_VERSION}</p></body></html>").unwrap();
+    html
+}
+
+pub fn render_json(path: &str, entries: &[Entry]) -> String {
+    let mut json = String::with_capacity(64 + 128 * entries.len());
+    write!(json, "{{\"path\":\"{}\",\"entries\":[", escape_json(path)).unwrap();
+    for (index, entry) in entries.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"name\":\"{}\",\"type\":\"{}\"", escape_json(&entry.name), if entry.is_dir { "directory" } else { "file" }).unwrap();
+        if !entry.is_dir {
+            write!(json, ",\"size\":{}", entry.size).unwrap();
+        }
+        if let Some(seconds) = entry.modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs()) {
+            write!(json, ",\"modified\":{seconds}").unwrap();
+        }
+        json.push('}');
+    }
+    json.push_str("]}\n");
+    json
+}
+
+fn escape_html(str: &str) -> String {
+    let mut result = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
@@ -2,6 +2,9 @@
  * Rusty HTTP Server - simple and scalable HTTP server
  * This is free and unencumbered software released into the public domain.
  */
+mod access_log;
+mod listing;
 mod web_handler;
 
-pub use web_handler::WebHandler;
+pub use access_log::AccessLog;
+pub use web_handler::{WebHandler, KeepAlive};
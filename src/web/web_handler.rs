@@ -9,106 +9,522 @@ use std::fs::{File, Metadata};
 use std::io::{Error as IoError, Result as IoResult, ErrorKind};
 use std::num::NonZeroUsize;
 use std::path::{PathBuf, Path, Component};
-use std::str::FromStr;
-use std::time::Duration;
+use std::net::{Shutdown, SocketAddr};
+use std::str;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use lazy_static::lazy_static;
+use httpdate::HttpDate;
 use log::{trace, debug, info, warn, log_enabled, Level};
-use mtcp_rs::TcpStream;
-use regex::bytes::Regex;
+use mtcp_rs::TcpError;
+
+use crate::{logging, metrics};
+use crate::net::Stream;
 
 use crate::http::content_type::ContentType;
-use crate::http::{ParseError, Method};
-use crate::server::Handler;
+use crate::http::{path, BodyError, EntityTag, ParseError, Method, RangeSet, Version};
+use crate::http::request::header_length;
+use crate::limiter::Limiter;
+use crate::server::{Handler, Listener, Rejection, ServerState};
+use crate::web::access_log::{AccessLog, Entry};
+use crate::web::listing::{self, SortKey};
 use crate::http::{Response, StatusCode, Request};
 
+const MAX_HEADER_LENGTH: usize = 1048576;
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+const REJECT_TIMEOUT: Duration = Duration::from_millis(250);
+const RETRY_AFTER: u64 = 5;
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
 thread_local! {
-    static BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+    static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+pub struct KeepAlive {
+    timeout: Duration,
+    max_requests: usize,
 }
 
 pub struct WebHandler {
     root_path: PathBuf,
     timeout: Option<Duration>,
+    keep_alive: Option<KeepAlive>,
+    max_body_size: usize,
+    allow_encoded_slashes: bool,
+    directory_listing: bool,
+    index_files: Vec<String>,
+    access_log: Option<AccessLog>,
+    metrics_path: Option<String>,
+    limiter: Option<Arc<Limiter>>,
+}
+
+struct Exchange<'a> {
+    id: usize,
+    peer_addr: Option<SocketAddr>,
+    time: SystemTime,
+    start: Instant,
+    request_line: Option<&'a str>,
+}
+
+impl KeepAlive {
+    pub fn new(timeout: Duration, max_requests: usize) -> Self {
+        Self {
+            timeout,
+            max_requests: max_requests.max(1),
+        }
+    }
 }
 
 impl WebHandler {
-    pub fn new(root_path: &Path, timeout: Option<Duration>) -> IoResult<Self> {
+    pub fn new(root_path: &Path, timeout: Option<Duration>, keep_alive: Option<KeepAlive>, max_body_size: usize, allow_encoded_slashes: bool, directory_listing: bool, index_files: &[String]) -> IoResult<Self> {
         let root_path = absolute(root_path)?;
         info!("Document root: {:?}", root_path);
         if !root_path.is_dir() {
             return Err(IoError::new(ErrorKind::NotFound, "Root directory not found!"));
         }
+        if index_files.iter().any(|name| Self::check_filename(OsStr::new(name)).is_none()) {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Invalid index file name!"));
+        }
         Ok(Self {
             root_path,
             timeout,
+            keep_alive,
+            max_body_size,
+            allow_encoded_slashes,
+            directory_listing,
+            index_files: index_files.to_vec(),
+            access_log: None,
+            metrics_path: None,
+            limiter: None,
         })
     }
 
-    fn parse_request(&self, id: usize, mut stream: TcpStream, buffer: &mut Vec<u8>) -> IoResult<()> {
-        buffer.clear();
-        match stream.read_all_timeout(buffer, self.timeout, None, NonZeroUsize::new(1048576), header_is_complete) {
-            Ok(_) => {
-                let response = self.process_request(id, Request::try_from(&buffer[..])?);
-                response.send(stream, self.timeout)
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    pub fn with_metrics_path(mut self, path: &str) -> Self {
+        self.metrics_path = Some(path.to_owned());
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Arc<Limiter>) -> Self {
+        self.limiter = Some(limiter).filter(|limiter| limiter.is_enabled());
+        self
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_ref()
+    }
+
+    fn process_connection(&self, id: usize, stream: &mut Stream, buffer: &mut Vec<u8>, state: &ServerState) -> IoResult<()> {
+        let peer_addr = stream.peer_addr();
+        let mut count = 0usize;
+        loop {
+            let idle_timeout = self.keep_alive.as_ref().filter(|_| count > 0).map(|keep_alive| keep_alive.timeout);
+            match self.read_request(stream, buffer, idle_timeout, state) {
+                Ok(true) => {},
+                Ok(false) => {
+                    debug!("{}Connection closed after {count} request(s)", logging::id_prefix(id));
+                    return Ok(());
+                },
+                Err(error) => {
+                    let response = self.read_error(id, error, buffer)?;
+                    return self.send_error(stream, response, count + 1, &Exchange::new(id, peer_addr, buffer));
+                },
+            }
+            count += 1;
+            let (keep_alive, length, surplus) = {
+                let exchange = Exchange::new(id, peer_addr, buffer);
+                let (mut request, length) = match Request::parse(&buffer[..]) {
+                    Ok(result) => {
+                        logging::set_request(result.0.method(), result.0.path());
+                        result
+                    },
+                    Err(error) => return self.send_error(stream, self.parse_error(id, error), count, &exchange),
+                };
+                match request.read_body(stream, &buffer[length..], self.max_body_size, self.timeout) {
+                    Ok(remainder) => {
+                        let version = request.version();
+                        let mut keep_alive = self.is_keep_alive(&request, count) && !state.is_draining();
+                        let mut response = match self.check_rate_limit(id, &request, peer_addr) {
+                            Some(response) => response,
+                            None => self.process_request(id, &request, state),
+                        };
+                        if matches!(request.method(), Method::HEAD) {
+                            response = response.without_body();
+                        }
+                        if response.is_streamed() && (version == Version::HTTP10) {
+                            response = response.without_chunking();
+                            keep_alive = false;
+                        }
+                        self.send_response(stream, self.connection_headers(response, keep_alive, count), self.timeout, &exchange, Some(&request))?;
+                        (keep_alive, length + remainder.consumed(), remainder.into_surplus())
+                    },
+                    Err(error) => {
+                        let response = self.body_error(id, error)?;
+                        return self.send_error(stream, response, count, &exchange);
+                    },
+                }
+            };
+            buffer.drain(..length);
+            buffer.extend_from_slice(&surplus);
+            if !keep_alive {
+                debug!("{}Closing connection after {count} request(s)", logging::id_prefix(id));
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_request(&self, stream: &mut Stream, buffer: &mut Vec<u8>, idle_timeout: Option<Duration>, state: &ServerState) -> Result<bool, TcpError> {
+        if header_is_complete(buffer) {
+            return Ok(true);
+        }
+        if buffer.is_empty() {
+            let mut temp = [0u8; 4096];
+            let deadline = idle_timeout.or(self.timeout).map(|timeout| Instant::now() + timeout);
+            loop {
+                if state.is_draining() {
+                    return Ok(false);
+                }
+                let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                if remaining.is_some_and(|time| time.is_zero()) {
+                    return match idle_timeout {
+                        Some(_) => Ok(false),
+                        None => Err(TcpError::TimedOut),
+                    };
+                }
+                match stream.read_timeout(&mut temp, Some(remaining.map_or(IDLE_POLL_INTERVAL, |time| time.min(IDLE_POLL_INTERVAL)))) {
+                    Ok(0) => return Ok(false),
+                    Ok(length) => break buffer.extend_from_slice(&temp[..length]),
+                    Err(TcpError::TimedOut) => continue,
+                    Err(error) => return Err(error),
+                }
+            }
+            if header_is_complete(buffer) {
+                return Ok(true);
+            }
+        }
+        match stream.read_all_timeout(buffer, self.timeout, None, NonZeroUsize::new(MAX_HEADER_LENGTH), header_is_complete) {
+            Ok(_) => Ok(true),
+            Err(TcpError::Incomplete) if buffer.is_empty() => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn send_response(&self, stream: &mut Stream, response: Response, timeout: Option<Duration>, exchange: &Exchange, request: Option<&Request>) -> IoResult<()> {
+        let status = response.status_code();
+        logging::set_status(status as u16);
+        stream.mark();
+        let result = response.send(stream, timeout);
+        match result.as_ref() {
+            Ok(_) => info!("{}Response: {status} {} ({} bytes)", logging::id_prefix(exchange.id), status.reason_phrase(), stream.written_since_mark()),
+            Err(error) => warn!("{}Response: {status} {} failed after {} bytes: {error}", logging::id_prefix(exchange.id), status.reason_phrase(), stream.written_since_mark()),
+        }
+        let method = request.map(|request| request.method().to_string());
+        metrics::request_completed(method.as_deref().unwrap_or("UNKNOWN"), status as u16, stream.written_since_mark(), exchange.start.elapsed());
+        if let Some(access_log) = self.access_log.as_ref() {
+            access_log.write(&Entry {
+                peer_addr: exchange.peer_addr,
+                time: exchange.time,
+                request_line: exchange.request_line,
+                request,
+                status,
+                bytes: stream.written_since_mark(),
+                duration: exchange.start.elapsed(),
+            });
+        }
+        result
+    }
+
+    fn send_error(&self, stream: &mut Stream, response: Response, count: usize, exchange: &Exchange) -> IoResult<()> {
+        self.send_response(stream, self.connection_headers(response, false, count), self.timeout, exchange, None)?;
+        linger(stream, LINGER_TIMEOUT);
+        Ok(())
+    }
+
+    fn is_keep_alive(&self, request: &Request, count: usize) -> bool {
+        let Some(keep_alive) = self.keep_alive.as_ref() else {
+            return false;
+        };
+        if count >= keep_alive.max_requests {
+            return false;
+        }
+        match request.headers() {
+            Some(headers) => match request.version() {
+                Version::HTTP10 => headers.contains_token("Connection", "keep-alive"),
+                Version::HTTP11 => !headers.contains_token("Connection", "close"),
             },
-            Err(error) => Err(error.into()),
+            None => request.version() == Version::HTTP11,
+        }
+    }
+
+    fn connection_headers(&self, response: Response, keep_alive: bool, count: usize) -> Response {
+        match self.keep_alive.as_ref().filter(|_| keep_alive) {
+            Some(settings) => response
+                .with_header("Connection", "keep-alive")
+                .with_header("Keep-Alive", format!("timeout={}, max={}", settings.timeout.as_secs().max(1), settings.max_requests - count)),
+            None => response.with_header("Connection", "close"),
         }
     }
 
-    fn process_request(&self, id: usize, request: Request) -> Response {
+    fn check_rate_limit(&self, id: usize, request: &Request, peer_addr: Option<SocketAddr>) -> Option<Response> {
+        let (limiter, peer_addr) = self.limiter.as_ref().zip(peer_addr).filter(|_| !self.is_builtin_path(request.path()))?;
+        let retry_after = limiter.check_request(peer_addr.ip()).err()?;
+        warn!("{}Client {} has exceeded the request rate limit!", logging::id_prefix(id), peer_addr.ip());
+        Some(Self::error_too_many_requests(retry_after))
+    }
+
+    fn process_request(&self, id: usize, request: &Request, state: &ServerState) -> Response {
         let request_method = request.method();
         if !log_enabled!(Level::Trace) {
-            debug!("[id:{id:X}] Request: {request_method} {:?}", request.path());
+            debug!("{}Request: {request_method} {:?}", logging::id_prefix(id), request.path());
         } else {
-            trace!("[id:{id:X}] {:?}", request);
+            trace!("{}{:?}", logging::id_prefix(id), request);
         }
         match request_method {
-            Method::GET => self.create_response(id, request, true),
-            Method::HEAD => self.create_response(id, request, false),
+            Method::GET => self.create_response(id, request, state, true),
+            Method::HEAD => self.create_response(id, request, state, false),
             _ => {
-                warn!("[id:{id:X}] Method {:?} is not allowed!", request_method);
+                warn!("{}Method {:?} is not allowed!", logging::id_prefix(id), request_method);
                 Self::error_method_not_allowed()
             },
         }
     }
 
-    fn create_response(&self, id: usize, request: Request, transmit_data: bool) -> Response {
-        let request_path = request.path();
-        if let Some(full_path) = Self::sanitize_path(request_path).map(|path| self.root_path.join(path)) {
+    fn read_error(&self, id: usize, error: TcpError, buffer: &[u8]) -> IoResult<Response> {
+        warn!("{}Failed to read the request header: {error}", logging::id_prefix(id));
+        match error {
+            TcpError::TimedOut => Ok(Self::error_request_timeout()),
+            TcpError::TooBig => match buffer.iter().skip_while(|&&c| (c == b'\r') || (c == b'\n')).any(|&c| c == b'\n') {
+                true => Ok(Self::error_header_fields_too_large()),
+                false => Ok(Self::error_uri_too_long()),
+            },
+            TcpError::Incomplete => Err(IoError::new(ErrorKind::UnexpectedEof, error)),
+            other => Err(other.into()),
+        }
+    }
+
+    fn parse_error(&self, id: usize, error: ParseError) -> Response {
+        warn!("{}Failed to parse the request: {error}", logging::id_prefix(id));
+        match error.status_code() {
+            StatusCode::HttpVersionNotSupported => Self::error_version_not_supported(),
+            StatusCode::NotImplemented => Self::error_not_implemented(),
+            StatusCode::UriTooLong => Self::error_uri_too_long(),
+            _ => Self::error_bad_request(),
+        }
+    }
+
+    fn body_error(&self, id: usize, error: BodyError) -> IoResult<Response> {
+        warn!("{}Failed to read the request body: {error}", logging::id_prefix(id));
+        match error {
+            BodyError::Framing | BodyError::Chunk => Ok(Self::error_bad_request()),
+            BodyError::Unsupported => Ok(Self::error_not_implemented()),
+            BodyError::Expectation => Ok(Self::error_expectation_failed()),
+            BodyError::TooLarge => Ok(Self::error_payload_too_large()),
+            BodyError::TrailerTooLarge => Ok(Self::error_header_fields_too_large()),
+            BodyError::TimedOut => Ok(Self::error_request_timeout()),
+            BodyError::Incomplete => Err(IoError::new(ErrorKind::UnexpectedEof, error)),
+            BodyError::Failed(inner) => Err(inner),
+        }
+    }
+
+    fn create_response(&self, id: usize, request: &Request, state: &ServerState, transmit_data: bool) -> Response {
+        if let Some((status, text, content_type)) = self.builtin_response(id, request.path(), state) {
+            let response = match transmit_data {
+                true => Response::from_string(status, text, Some(content_type)),
+                false => Response::new(status, Some(text.len() as u64), Some(content_type)),
+            };
+            return response.with_header("Cache-Control", "no-store");
+        }
+        let request_path = match request.decoded_path(self.allow_encoded_slashes) {
+            Ok(path) => path,
+            Err(error) => return self.parse_error(id, error),
+        };
+        if let Some(full_path) = Self::sanitize_path(&request_path).map(|path| self.root_path.join(path)) {
             if let Ok(file_info) = full_path.metadata() {
-                trace!("[id:{id:X}] File meta information: {:?}", file_info);
+                trace!("{}File meta information: {:?}", logging::id_prefix(id), file_info);
                 if !file_info.is_dir() {
-                    Self::serve_file_response(id, &full_path, &file_info, transmit_data)
+                    Self::serve_file_response(id, request, &full_path, &file_info, transmit_data)
                 } else {
-                    warn!("[id:{id:X}] Directory listing is forbidden!");
-                    Self::error_forbidden()
+                    self.directory_response(id, request, &request_path, &full_path, transmit_data)
                 }
             } else {
-                warn!("[id:{id:X}] Requested resource {:?} could not be found!", full_path);
+                warn!("{}Requested resource {:?} could not be found!", logging::id_prefix(id), full_path);
                 Self::error_not_found()
             }
         } else {
-            warn!("[id:{id:X}] Request path {:?} is invalid!", request_path);
+            warn!("{}Request path {:?} is invalid!", logging::id_prefix(id), request_path);
             Self::error_not_found()
         }
     }
 
-    fn serve_file_response(id: usize, full_path: &Path, file_info: &Metadata, transmit_data: bool) -> Response {
+    fn is_builtin_path(&self, path: &str) -> bool {
+        matches!(path, "/healthz" | "/readyz") || (self.metrics_path.as_deref() == Some(path))
+    }
+
+    fn builtin_response(&self, id: usize, path: &str, state: &ServerState) -> Option<(StatusCode, String, ContentType)> {
+        match path {
+            "/healthz" => Some((StatusCode::Ok, String::from("ok\n"), ContentType::Text)),
+            "/readyz" => match state.is_ready() {
+                true => Some((StatusCode::Ok, String::from("ready\n"), ContentType::Text)),
+                false => {
+                    debug!("{}Server is not ready!", logging::id_prefix(id));
+                    Some((StatusCode::ServiceUnavailable, String::from("not ready\n"), ContentType::Text))
+                },
+            },
+            path if self.metrics_path.as_deref() == Some(path) => {
+                debug!("{}Serving metrics", logging::id_prefix(id));
+                Some((StatusCode::Ok, metrics::render(), ContentType::Prometheus))
+            },
+            _ => None,
+        }
+    }
+
+    fn directory_response(&self, id: usize, request: &Request, request_path: &str, full_path: &Path, transmit_data: bool) -> Response {
+        if !request_path.ends_with('/') {
+            debug!("{}Redirecting to directory: {:?}", logging::id_prefix(id), request_path);
+            return Self::redirect_to_directory(request, request_path);
+        }
+        for index_file in self.index_files.iter() {
+            let index_path = full_path.join(index_file);
+            if let Some(file_info) = index_path.metadata().ok().filter(|file_info| !file_info.is_dir()) {
+                return Self::serve_file_response(id, request, &index_path, &file_info, transmit_data);
+            }
+        }
+        if !self.directory_listing {
+            warn!("{}Directory listing is forbidden!", logging::id_prefix(id));
+            return Self::error_forbidden();
+        }
+        match listing::read_entries(full_path, |name| Self::check_filename(name).is_some()) {
+            Ok(mut entries) => {
+                info!("{}Sending directory listing: {:?} ({} entries)", logging::id_prefix(id), full_path, entries.len());
+                let query = request.query();
+                let parameter = |name: &str| query.and_then(|query| query.values(name)).and_then(|mut values| values.next());
+                let json = parameter("format").map_or_else(|| request.headers().is_some_and(|headers| headers.values("Accept").is_some_and(|mut values| values.any(|value| value.contains("application/json")))), |format| format == "json");
+                let sort_key = parameter("sort").and_then(SortKey::parse).unwrap_or(SortKey::Name);
+                let descending = parameter("order") == Some("desc");
+                listing::sort_entries(&mut entries, sort_key, descending);
+                let (text, content_type) = match json {
+                    true => (listing::render_json(request_path, &entries), ContentType::JSON),
+                    false => (listing::render_html(request_path, &entries, sort_key, descending), ContentType::HTML),
+                };
+                let response = match transmit_data {
+                    true => Response::from_string(StatusCode::Ok, text, Some(content_type)),
+                    false => Response::new(StatusCode::Ok, Some(text.len() as u64), Some(content_type)),
+                };
+                response.with_header("Vary", "Accept")
+            },
+            Err(error) => {
+                warn!("{}Directory {:?} could not be read: {error}", logging::id_prefix(id), full_path);
+                Self::error_internal()
+            },
+        }
+    }
+
+    fn redirect_to_directory(request: &Request, request_path: &str) -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>301 Moved Permanently</title><h1>301 Moved Permanently</h1><h3>The requested resource has been assigned a new permanent URI.</h3>\n";
+        let query = &request.target()[request.path().len()..];
+        Response::from_text(StatusCode::MovedPermanently, HTML_TEXT, Some(ContentType::HTML)).with_header("Location", format!("{}/{query}", path::encode(request_path)))
+    }
+
+    fn serve_file_response(id: usize, request: &Request, full_path: &Path, file_info: &Metadata, transmit_data: bool) -> Response {
+        let etag = EntityTag::from_metadata(file_info);
+        let last_modified = file_info.modified().ok().map(HttpDate::from);
+        let content_type = ContentType::from_path(full_path);
+
+        match Self::check_preconditions(request, &etag, last_modified) {
+            Some(StatusCode::NotModified) => {
+                info!("{}File {:?} has not been modified", logging::id_prefix(id), full_path);
+                return Self::file_headers(Response::new(StatusCode::NotModified, None, None), &etag, last_modified);
+            },
+            Some(_) => {
+                warn!("{}Precondition for file {:?} has failed!", logging::id_prefix(id), full_path);
+                return Self::error_precondition_failed();
+            },
+            None => {},
+        }
+
         match File::open(full_path) {
             Ok(file) => if transmit_data {
-                info!("[id:{id:X}] Sending file: {:?} (size: {:?} bytes)", full_path, file_info.len());
-                Response::from_file(StatusCode::Ok, file, ContentType::from_path(full_path))
+                match Self::requested_ranges(request, file_info, &etag, last_modified) {
+                    Some(RangeSet::Satisfiable(mut ranges)) => if ranges.len() == 1 {
+                        let range = ranges.pop().unwrap();
+                        info!("{}Sending file: {:?} (range: {range}, size: {:?} bytes)", logging::id_prefix(id), full_path, file_info.len());
+                        Self::file_headers(Response::from_file_range(file, range, file_info.len(), content_type), &etag, last_modified)
+                    } else {
+                        info!("{}Sending file: {:?} (ranges: {}, size: {:?} bytes)", logging::id_prefix(id), full_path, ranges.len(), file_info.len());
+                        Self::file_headers(Response::from_file_ranges(file, ranges, file_info.len(), content_type), &etag, last_modified)
+                    },
+                    Some(RangeSet::Unsatisfiable) => {
+                        warn!("{}Requested range of file {:?} is not satisfiable!", logging::id_prefix(id), full_path);
+                        Self::error_range_not_satisfiable(file_info.len())
+                    },
+                    None => {
+                        info!("{}Sending file: {:?} (size: {:?} bytes)", logging::id_prefix(id), full_path, file_info.len());
+                        Self::file_headers(Response::from_file(StatusCode::Ok, file, content_type), &etag, last_modified)
+                    },
+                }
             } else {
-                info!("[id:{id:X}] File content-length is: {:?} bytes", file_info.len());
-                Response::new(StatusCode::Ok, Some(file_info.len()), ContentType::from_path(full_path))
+                info!("{}File content-length is: {:?} bytes", logging::id_prefix(id), file_info.len());
+                Self::file_headers(Response::new(StatusCode::Ok, Some(file_info.len()), content_type), &etag, last_modified)
             },
             Err(_) => {
-                warn!("[id:{id:X}] File {:?} could not be opened!", full_path);
+                warn!("{}File {:?} could not be opened!", logging::id_prefix(id), full_path);
                 Self::error_internal()
             },
         }
     }
 
+    fn file_headers(response: Response, etag: &EntityTag, last_modified: Option<HttpDate>) -> Response {
+        let response = response.with_header("Accept-Ranges", "bytes").with_header("ETag", etag);
+        match last_modified {
+            Some(date) => response.with_header("Last-Modified", date),
+            None => response,
+        }
+    }
+
+    fn check_preconditions(request: &Request, etag: &EntityTag, last_modified: Option<HttpDate>) -> Option<StatusCode> {
+        let headers = request.headers()?;
+        if let Some(mut if_match) = headers.values("If-Match") {
+            if !if_match.any(|value| etag.matches_any(value, false)) {
+                return Some(StatusCode::PreconditionFailed);
+            }
+        } else if let Some(since) = headers.get("If-Unmodified-Since").and_then(|value| value.parse::<HttpDate>().ok()) {
+            if last_modified.is_none_or(|date| date > since) {
+                return Some(StatusCode::PreconditionFailed);
+            }
+        }
+        if let Some(mut if_none_match) = headers.values("If-None-Match") {
+            if if_none_match.any(|value| etag.matches_any(value, true)) {
+                return Some(StatusCode::NotModified);
+            }
+        } else if let Some(since) = headers.get("If-Modified-Since").and_then(|value| value.parse::<HttpDate>().ok()) {
+            if last_modified.is_some_and(|date| date <= since) {
+                return Some(StatusCode::NotModified);
+            }
+        }
+        None
+    }
+
+    fn requested_ranges(request: &Request, file_info: &Metadata, etag: &EntityTag, last_modified: Option<HttpDate>) -> Option<RangeSet> {
+        let range = request.header("Range")?;
+        if let Some(if_range) = request.header("If-Range") {
+            if !Self::if_range_matches(if_range, etag, last_modified) {
+                return None;
+            }
+        }
+        RangeSet::parse(range, file_info.len())
+    }
+
+    fn if_range_matches(if_range: &str, etag: &EntityTag, last_modified: Option<HttpDate>) -> bool {
+        match EntityTag::parse(if_range) {
+            Some(other) => etag.strong_eq(&other),
+            None => if_range.parse::<HttpDate>().ok().zip(last_modified).is_some_and(|(date, modified)| date == modified),
+        }
+    }
+
     fn sanitize_path(path_str: &str) -> Option<PathBuf> {
         static DELIM: [ char; 2 ] = [ '/', '\\' ];
         let iterator = Path::new(path_str.trim_start_matches(DELIM)).components();
@@ -123,9 +539,7 @@ impl WebHandler {
             }
         }
 
-        (!components.is_empty())
-            .then(|| components.into_iter().collect::<PathBuf>())
-            .or_else(|| PathBuf::from_str("index.html").ok())
+        Some(components.into_iter().collect::<PathBuf>())
     }
 
     fn check_filename(name: &OsStr) -> Option<&OsStr> {
@@ -134,6 +548,11 @@ impl WebHandler {
         (!(name_str.contains(ILLEGAL_CHARS) || name_str.starts_with('.'))).then_some(name)
     }
 
+    fn error_bad_request() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 400</title><h1>400 Bad Request</h1><h3>The server cannot process the request due to a malformed request syntax.</h3>\n";
+        Response::from_text(StatusCode::BadRequest, HTML_TEXT, Some(ContentType::HTML))
+    }
+
     fn error_forbidden() -> Response {
         const HTML_TEXT: &str = "<!doctype html><title>Error 403</title><h1>403 Forbidden</h1><h3>You don't have permission to access the requested resource on this server.</h3>\n";
         Response::from_text(StatusCode::Forbidden, HTML_TEXT, Some(ContentType::HTML))
@@ -149,31 +568,123 @@ impl WebHandler {
         Response::from_text(StatusCode::MethodNotAllowed, HTML_TEXT, Some(ContentType::HTML))
     }
 
+    fn error_request_timeout() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 408</title><h1>408 Request Timeout</h1><h3>The server timed out waiting for the request.</h3>\n";
+        Response::from_text(StatusCode::RequestTimeout, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_precondition_failed() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 412</title><h1>412 Precondition Failed</h1><h3>One or more conditions given in the request header fields evaluated to false.</h3>\n";
+        Response::from_text(StatusCode::PreconditionFailed, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_payload_too_large() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 413</title><h1>413 Payload Too Large</h1><h3>The request entity is larger than the limit defined by the server.</h3>\n";
+        Response::from_text(StatusCode::PayloadTooLarge, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_uri_too_long() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 414</title><h1>414 URI Too Long</h1><h3>The request target is longer than the server is willing to interpret.</h3>\n";
+        Response::from_text(StatusCode::UriTooLong, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_range_not_satisfiable(size: u64) -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 416</title><h1>416 Range Not Satisfiable</h1><h3>None of the ranges in the request's Range header overlap the current extent of the selected resource.</h3>\n";
+        Response::from_text(StatusCode::RangeNotSatisfiable, HTML_TEXT, Some(ContentType::HTML)).with_header("Content-Range", format!("bytes */{size}"))
+    }
+
+    fn error_expectation_failed() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 417</title><h1>417 Expectation Failed</h1><h3>The expectation given in the request's Expect header could not be met by the server.</h3>\n";
+        Response::from_text(StatusCode::ExpectationFailed, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_too_many_requests(retry_after: Duration) -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 429</title><h1>429 Too Many Requests</h1><h3>The client has sent too many requests, please try again later.</h3>\n";
+        Response::from_text(StatusCode::TooManyRequests, HTML_TEXT, Some(ContentType::HTML))
+            .with_header("Retry-After", retry_after.as_secs_f64().ceil().max(1.0))
+    }
+
+    fn error_header_fields_too_large() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 431</title><h1>431 Request Header Fields Too Large</h1><h3>The server is unwilling to process the request because its header fields are too large.</h3>\n";
+        Response::from_text(StatusCode::RequestHeaderFieldsTooLarge, HTML_TEXT, Some(ContentType::HTML))
+    }
+
     fn error_internal() -> Response {
         const HTML_TEXT: &str = "<!doctype html><title>Error 500</title><h1>500 Internal Server Error</h1><h3>The server encountered an internal error and was unable to complete your request.</h3>\n";
         Response::from_text(StatusCode::InternalServerError, HTML_TEXT, Some(ContentType::HTML))
     }
+
+    fn error_not_implemented() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 501</title><h1>501 Not Implemented</h1><h3>The server does not support the functionality required to fulfill the request.</h3>\n";
+        Response::from_text(StatusCode::NotImplemented, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_service_unavailable() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 503</title><h1>503 Service Unavailable</h1><h3>The server is temporarily unable to handle the request, please try again later.</h3>\n";
+        Response::from_text(StatusCode::ServiceUnavailable, HTML_TEXT, Some(ContentType::HTML))
+    }
+
+    fn error_version_not_supported() -> Response {
+        const HTML_TEXT: &str = "<!doctype html><title>Error 505</title><h1>505 HTTP Version Not Supported</h1><h3>The HTTP version used in the request is not supported by the server.</h3>\n";
+        Response::from_text(StatusCode::HttpVersionNotSupported, HTML_TEXT, Some(ContentType::HTML))
+    }
 }
 
 impl Handler for WebHandler {
-    fn handle_request(&self, id: usize, stream: TcpStream) -> IoResult<()> {
+    fn handle_request(&self, id: usize, listener: &Listener, mut stream: Stream, state: &ServerState) -> IoResult<()> {
+        debug!("{}Serving connection on listener #{}: {}", logging::id_prefix(id), listener.index(), listener.address());
         BUFFER.with(|buffer| {
-            self.parse_request(id, stream, &mut buffer.borrow_mut())
+            let mut buffer = buffer.borrow_mut();
+            buffer.clear();
+            self.process_connection(id, &mut stream, &mut buffer, state)
         })
     }
+
+    fn reject_request(&self, id: usize, listener: &Listener, mut stream: Stream, reason: Rejection) -> IoResult<()> {
+        debug!("{}Rejecting connection on listener #{}: {}", logging::id_prefix(id), listener.index(), listener.address());
+        let exchange = Exchange::new(id, stream.peer_addr(), &[]);
+        let response = match reason {
+            Rejection::Limited(retry_after) => Self::error_too_many_requests(retry_after),
+            _ => Self::error_service_unavailable().with_header("Retry-After", RETRY_AFTER),
+        };
+        self.send_response(&mut stream, self.connection_headers(response, false, 1), Some(REJECT_TIMEOUT), &exchange, None)?;
+        linger(&mut stream, Duration::ZERO);
+        Ok(())
+    }
 }
 
-impl From<ParseError> for IoError {
-    fn from(err: ParseError) -> Self {
-        IoError::new(ErrorKind::InvalidData, err)
+impl<'a> Exchange<'a> {
+    fn new(id: usize, peer_addr: Option<SocketAddr>, buffer: &'a [u8]) -> Self {
+        let start = buffer.iter().position(|&c| (c != b'\r') && (c != b'\n')).unwrap_or(buffer.len());
+        let request_line = buffer[start..].iter().position(|&c| c == b'\n')
+            .and_then(|length| str::from_utf8(&buffer[start..start + length]).ok())
+            .map(|line| line.trim_end_matches('\r'));
+        Self {
+            id,
+            peer_addr,
+            time: SystemTime::now(),
+            start: Instant::now(),
+            request_line,
+        }
     }
 }
 
-fn header_is_complete(buffer: &[u8]) -> bool {
-    lazy_static! {
-        static ref END_MARKER: Regex = Regex::new(r"\x0D\x0A[\x09\x0B\x0C\x20]*\x0D\x0A").unwrap();
+fn linger(stream: &mut Stream, timeout: Duration) {
+    if stream.shutdown(Shutdown::Write).is_ok() {
+        let mut temp = [0u8; 4096];
+        let mut total = 0usize;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match stream.read_timeout(&mut temp, Some(deadline.saturating_duration_since(Instant::now()))) {
+                Ok(length) if (length > 0) && (total < MAX_HEADER_LENGTH) => total += length,
+                _ => break,
+            }
+        }
     }
-    END_MARKER.is_match(buffer)
+}
+
+fn header_is_complete(buffer: &[u8]) -> bool {
+    header_length(buffer).is_some()
 }
 
 pub fn absolute(path: &Path) -> IoResult<PathBuf> {
@@ -183,3 +694,177 @@ pub fn absolute(path: &Path) -> IoResult<PathBuf> {
         Ok(path.to_path_buf())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::{fs, process, io::Read, os::unix::net::UnixStream};
+    #[cfg(unix)]
+    use std::io::Write;
+
+    #[cfg(unix)]
+    use crate::net::SocketStream;
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const EARLIER: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+    const LATER: &str = "Mon, 07 Nov 1994 08:49:37 GMT";
+
+    fn preconditions(headers: &str) -> Option<StatusCode> {
+        let text = format!("GET /file HTTP/1.1\r\n{headers}\r\n");
+        let (request, _) = Request::parse(text.as_bytes()).unwrap();
+        WebHandler::check_preconditions(&request, &EntityTag::new(false, "abc"), MODIFIED.parse().ok())
+    }
+
+    #[test]
+    fn if_match() {
+        assert_eq!(preconditions("If-Match: \"abc\"\r\n"), None);
+        assert_eq!(preconditions("If-Match: *\r\n"), None);
+        assert_eq!(preconditions("If-Match: \"xyz\"\r\n"), Some(StatusCode::PreconditionFailed));
+        assert_eq!(preconditions("If-Match: W/\"abc\"\r\n"), Some(StatusCode::PreconditionFailed));
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        assert_eq!(preconditions(&format!("If-Unmodified-Since: {EARLIER}\r\n")), Some(StatusCode::PreconditionFailed));
+        assert_eq!(preconditions(&format!("If-Unmodified-Since: {MODIFIED}\r\n")), None);
+        assert_eq!(preconditions(&format!("If-Match: \"abc\"\r\nIf-Unmodified-Since: {EARLIER}\r\n")), None);
+        assert_eq!(preconditions(&format!("If-Match: \"xyz\"\r\nIf-Unmodified-Since: {LATER}\r\n")), Some(StatusCode::PreconditionFailed));
+    }
+
+    #[test]
+    fn if_none_match() {
+        assert_eq!(preconditions("If-None-Match: \"abc\"\r\n"), Some(StatusCode::NotModified));
+        assert_eq!(preconditions("If-None-Match: W/\"abc\"\r\n"), Some(StatusCode::NotModified));
+        assert_eq!(preconditions("If-None-Match: *\r\n"), Some(StatusCode::NotModified));
+        assert_eq!(preconditions("If-None-Match: \"xyz\"\r\n"), None);
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        assert_eq!(preconditions(&format!("If-Modified-Since: {MODIFIED}\r\n")), Some(StatusCode::NotModified));
+        assert_eq!(preconditions(&format!("If-Modified-Since: {EARLIER}\r\n")), None);
+        assert_eq!(preconditions(&format!("If-None-Match: \"xyz\"\r\nIf-Modified-Since: {LATER}\r\n")), None);
+        assert_eq!(preconditions(&format!("If-None-Match: \"abc\"\r\nIf-Modified-Since: {EARLIER}\r\n")), Some(StatusCode::NotModified));
+    }
+
+    #[test]
+    fn failed_match_takes_precedence_over_not_modified() {
+        assert_eq!(preconditions("If-Match: \"xyz\"\r\nIf-None-Match: \"abc\"\r\n"), Some(StatusCode::PreconditionFailed));
+        assert_eq!(preconditions(&format!("If-Unmodified-Since: {EARLIER}\r\nIf-Modified-Since: {LATER}\r\n")), Some(StatusCode::PreconditionFailed));
+    }
+
+    #[test]
+    fn ignore_invalid_dates() {
+        assert_eq!(preconditions("If-Unmodified-Since: yesterday\r\n"), None);
+        assert_eq!(preconditions("If-Modified-Since: yesterday\r\n"), None);
+    }
+
+    #[test]
+    fn if_range() {
+        let modified = MODIFIED.parse().ok();
+        assert!(WebHandler::if_range_matches("\"abc\"", &EntityTag::new(false, "abc"), modified));
+        assert!(!WebHandler::if_range_matches("W/\"abc\"", &EntityTag::new(false, "abc"), modified));
+        assert!(!WebHandler::if_range_matches("\"abc\"", &EntityTag::new(true, "abc"), modified));
+        assert!(WebHandler::if_range_matches(MODIFIED, &EntityTag::new(false, "abc"), modified));
+        assert!(!WebHandler::if_range_matches(EARLIER, &EntityTag::new(false, "abc"), modified));
+        assert!(!WebHandler::if_range_matches(MODIFIED, &EntityTag::new(false, "abc"), None));
+    }
+
+    #[cfg(unix)]
+    fn serve(name: &str, keep_alive: Option<KeepAlive>, requests: &str, close: bool) -> String {
+        let root_path = env::temp_dir().join(format!("rusty_httpd-{name}-{}", process::id()));
+        fs::create_dir_all(&root_path).unwrap();
+        fs::write(root_path.join("index.html"), "Hello\n").unwrap();
+        let handler = WebHandler::new(&root_path, Some(Duration::from_secs(5)), keep_alive, 1024, false, false, &[String::from("index.html")]).unwrap();
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        remote.write_all(requests.as_bytes()).unwrap();
+        if close {
+            remote.shutdown(Shutdown::Write).unwrap();
+        }
+        let mut stream = Stream::from(SocketStream::Unix(local));
+        let result = handler.process_connection(0, &mut stream, &mut Vec::new(), &ServerState::default());
+        drop(stream);
+        drop(fs::remove_dir_all(&root_path));
+        result.unwrap();
+        let mut output = String::new();
+        remote.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[cfg(unix)]
+    fn keep_alive() -> Option<KeepAlive> {
+        Some(KeepAlive::new(Duration::from_millis(300), 100))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn head_without_body_on_persistent_connection() {
+        let output = serve("head", keep_alive(), "HEAD /missing HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n", true);
+        let parts: Vec<&str> = output.split("\r\n\r\n").collect();
+        assert_eq!(parts.len(), 4, "{output}");
+        assert!(parts[0].starts_with("HTTP/1.1 404 Not Found\r\n") && parts[0].contains("\r\nConnection: keep-alive"));
+        assert!(parts[1].starts_with("HTTP/1.1 200 Ok\r\n") && parts[1].contains("\r\nContent-Length: 6\r\n"));
+        assert!(parts[2].starts_with("HTTP/1.1 200 Ok\r\n") && parts[2].contains("\r\nConnection: close"));
+        assert_eq!(parts[3], "Hello\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http10_closes_by_default() {
+        let output = serve("http10", keep_alive(), "GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n", true);
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 1);
+        assert!(output.contains("\r\nConnection: close\r\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http10_keeps_alive_on_request() {
+        let output = serve("http10-keep-alive", keep_alive(), "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET / HTTP/1.0\r\n\r\n", true);
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 2);
+        assert!(output.contains("\r\nConnection: keep-alive\r\nKeep-Alive: timeout=1, max=99\r\n"));
+        assert!(output.ends_with("\r\nConnection: close\r\n\r\nHello\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http11_keeps_alive_by_default() {
+        let output = serve("http11", keep_alive(), "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n", true);
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 2);
+        assert_eq!(output.matches("\r\nConnection: keep-alive\r\n").count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http11_connection_close() {
+        let output = serve("close", keep_alive(), "GET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n", true);
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 1);
+        assert!(output.contains("\r\nConnection: close\r\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipelined_requests() {
+        let output = serve("pipelined", keep_alive(), "GET / HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n", true);
+        let status: Vec<&str> = output.lines().filter(|line| line.starts_with("HTTP/1.1 ")).collect();
+        assert_eq!(status, ["HTTP/1.1 200 Ok", "HTTP/1.1 404 Not Found", "HTTP/1.1 200 Ok"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keep_alive_disabled() {
+        let output = serve("no-keep-alive", None, "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n", true);
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 1);
+        assert!(output.contains("\r\nConnection: close\r\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn close_after_idle_timeout() {
+        let start = Instant::now();
+        let output = serve("idle", keep_alive(), "GET / HTTP/1.1\r\n\r\n", false);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(output.matches("HTTP/1.1 200 Ok").count(), 1);
+        assert!(output.ends_with("\r\n\r\nHello\n"));
+    }
+}